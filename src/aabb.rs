@@ -21,6 +21,10 @@ impl AABB {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.x.min > self.x.max || self.y.min > self.y.max || self.z.min > self.z.max
+    }
+
     pub fn from_intervals(x: Interval, y: Interval, z: Interval) -> Self {
         let (x, y, z) = pad_to_minimus(x, y, z);
 
@@ -0,0 +1,116 @@
+use crate::sampler;
+
+// Walker's alias method, built with Vose's algorithm: after linear-time setup
+// it picks an index in proportion to its weight with one random number, in
+// constant time.
+
+pub struct AliasTable {
+    bins: Vec<Bin>,
+}
+
+struct Bin {
+    q: f64,       // probability of keeping this index rather than its alias
+    pmf: f64,     // probability of picking this index overall
+    alias: usize, // index taken otherwise
+}
+
+impl AliasTable {
+    // Weights must not be negative; if they are all zero every index is
+    // equally likely.
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let total: f64 = weights.iter().sum();
+        let pmf: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|w| w / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        };
+
+        let mut bins: Vec<Bin> = pmf
+            .iter()
+            .enumerate()
+            .map(|(i, &pmf)| Bin {
+                q: pmf * n as f64,
+                pmf,
+                alias: i,
+            })
+            .collect();
+
+        let (mut under, mut over): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| bins[i].q < 1.0);
+
+        while let (Some(&small), Some(&large)) = (under.last(), over.last()) {
+            under.pop();
+            over.pop();
+
+            bins[small].alias = large;
+            bins[large].q -= 1.0 - bins[small].q;
+
+            if bins[large].q < 1.0 {
+                under.push(large);
+            } else {
+                over.push(large);
+            }
+        }
+
+        // Whatever is left is 1 up to rounding.
+        for i in under.into_iter().chain(over) {
+            bins[i].q = 1.0;
+        }
+
+        Self { bins }
+    }
+
+    // An index and the probability of having picked it, or `None` if the
+    // table is empty.
+    pub fn sample(&self) -> Option<(usize, f64)> {
+        if self.bins.is_empty() {
+            return None;
+        }
+
+        let u = sampler::random_double() * self.bins.len() as f64;
+        let i = (u as usize).min(self.bins.len() - 1);
+        let index = if u - (i as f64) < self.bins[i].q {
+            i
+        } else {
+            self.bins[i].alias
+        };
+
+        Some((index, self.bins[index].pmf))
+    }
+
+    pub fn pmf(&self, index: usize) -> f64 {
+        self.bins[index].pmf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_matches_pmf() {
+        let weights = [1.0, 0.0, 3.0, 6.0, 0.5, 2.5];
+        let table = AliasTable::new(&weights);
+        let total: f64 = weights.iter().sum();
+
+        let n = 200_000;
+        let mut counts = [0; 6];
+        for _ in 0..n {
+            let (index, pmf) = table.sample().unwrap();
+            assert_eq!(pmf, table.pmf(index));
+            counts[index] += 1;
+        }
+
+        for (i, &weight) in weights.iter().enumerate() {
+            assert!((table.pmf(i) - weight / total).abs() < 1e-12);
+            let frequency = counts[i] as f64 / n as f64;
+            assert!(
+                (frequency - table.pmf(i)).abs() < 0.005,
+                "index {i}: frequency {frequency}, pmf {}",
+                table.pmf(i)
+            );
+        }
+        assert_eq!(counts[1], 0);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::color::Color;
+use crate::environment;
+use crate::texture::Texture;
+use crate::vec3::{self, Vec3};
+
+// What rays see when they leave the scene without hitting anything, by the
+// direction they leave in. The camera keeps one background for its own rays
+// and one for every other ray, which is how the scene is lit by it; see
+// `Camera::with_visible_background` and `Camera::with_lighting_background`.
+//
+// Backgrounds are found only by rays that happen to escape: nothing samples
+// them, and photon mapping, whose photons all leave lights, sees only the
+// light they cast directly. For a background that lights the scene strongly,
+// use an `EnvironmentLight` in the light list instead.
+
+pub trait Background: Send + Sync {
+    fn value(&self, direction: Vec3) -> Color;
+}
+
+// The same color in every direction.
+impl Background for Color {
+    fn value(&self, _direction: Vec3) -> Color {
+        *self
+    }
+}
+
+// Blends from `bottom` looking straight down to `top` looking straight up.
+pub struct Gradient {
+    bottom: Color,
+    top: Color,
+}
+
+impl Gradient {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+
+    // The white to light blue sky of "Ray Tracing in One Weekend".
+    pub fn sky() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for Gradient {
+    fn value(&self, direction: Vec3) -> Color {
+        let a = 0.5 * (vec3::unit_vector(direction).y() + 1.0);
+
+        (1.0 - a) * self.bottom + a * self.top
+    }
+}
+
+// A texture wrapped around the scene, looked up with the same
+// latitude-longitude coordinates as `EnvironmentLight` and the unit
+// direction as the point.
+pub struct TextureBackground {
+    tex: Box<dyn Texture>,
+}
+
+impl TextureBackground {
+    pub fn new(tex: Box<dyn Texture>) -> Self {
+        Self { tex }
+    }
+}
+
+impl Background for TextureBackground {
+    fn value(&self, direction: Vec3) -> Color {
+        let (u, v) = environment::uv(direction, 0.0);
+
+        // Textures put v = 0 at the bottom of an image.
+        self.tex.value(u, 1.0 - v, vec3::unit_vector(direction))
+    }
+}
@@ -0,0 +1,655 @@
+use core::f64;
+use std::sync::Mutex;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light;
+use crate::material::ScatterRecord;
+use crate::pdf::{EmissionPdf, Pdf};
+use crate::ray::Ray;
+use crate::vec3::{self, Point3, Vec3};
+
+// Bidirectional path tracing after Veach's thesis and PBRT's BDPT integrator.
+// A camera subpath and a light subpath are traced independently, every prefix
+// pair (s light vertices, t camera vertices) is connected, and each strategy is
+// weighted with the balance heuristic. Strategies with t == 1 land in an
+// arbitrary pixel and are splatted into a shared buffer.
+
+struct Scene<'a> {
+    cam: &'a Camera,
+    world: &'a dyn Hittable,
+    lights: &'a dyn Hittable,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+    Medium,
+}
+
+struct Vertex<'a> {
+    kind: VertexKind,
+    p: Point3,
+    n: Vec3, // outward normal for lights, facing `r_in` for surfaces
+    rec: Option<HitRecord<'a>>,
+    r_in: Ray,
+    beta: Color,
+    delta: bool,
+    connectible: bool,
+    pdf_fwd: f64, // area density of sampling this vertex from its predecessor
+    pdf_rev: f64, // area density of sampling it from its successor
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(p: Point3, n: Vec3, beta: Color, time: f64) -> Self {
+        Self {
+            kind: VertexKind::Camera,
+            p,
+            n,
+            rec: None,
+            r_in: Ray::new(p, n, time),
+            beta,
+            delta: false,
+            connectible: true,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    // `rec` must carry the outward normal of the emitter.
+    fn light(rec: HitRecord<'a>, beta: Color, pdf_fwd: f64, time: f64) -> Self {
+        Self {
+            kind: VertexKind::Light,
+            p: rec.p,
+            n: rec.normal,
+            r_in: Ray::new(rec.p, rec.normal, time),
+            rec: Some(rec),
+            beta,
+            delta: false,
+            connectible: true,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn surface(rec: HitRecord<'a>, r_in: Ray, beta: Color) -> Self {
+        let kind = if rec.mat.is_volumetric() {
+            VertexKind::Medium
+        } else {
+            VertexKind::Surface
+        };
+
+        Self {
+            kind,
+            p: rec.p,
+            n: rec.normal,
+            rec: Some(rec),
+            r_in,
+            beta,
+            delta: false,
+            connectible: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn on_surface(&self) -> bool {
+        matches!(self.kind, VertexKind::Surface | VertexKind::Light)
+    }
+
+    fn time(&self) -> f64 {
+        self.r_in.time()
+    }
+
+    // Samples the material to classify the vertex and to continue the walk.
+    // Returns the scattered ray, the throughput factor, and the solid angle
+    // densities of the forward and reverse directions.
+    fn scatter(&mut self) -> Option<(Ray, Color, f64, f64)> {
+        let rec = self.rec.as_ref()?;
+
+        match rec.mat.scatter(self.r_in, rec)? {
+            ScatterRecord::Pdf { pdf_ptr, .. } => {
+                self.connectible = true;
+
+                let scattered = rec.mat.scattered_ray(self.r_in, rec, pdf_ptr.generate()?);
+                let pdf_fwd = pdf_ptr.value(scattered.direction());
+                let f = rec.mat.eval(self.r_in, rec, scattered);
+                if pdf_fwd <= 0.0 || is_black(f) {
+                    return None;
+                }
+
+                let reversed = scattered.continued(scattered.at(1.0), -scattered.direction());
+                let toward_prev = self.r_in.continued(rec.p, -self.r_in.direction());
+                let pdf_rev = rec.mat.scattering_pdf(reversed, rec, toward_prev);
+
+                Some((scattered, f / pdf_fwd, pdf_fwd, pdf_rev))
+            }
+            ScatterRecord::SkipPdf { attenuation, ray } => {
+                self.delta = true;
+
+                Some((*ray, attenuation, 0.0, 0.0))
+            }
+        }
+    }
+
+    // BSDF times cosine towards `next`; zero for endpoints.
+    fn f(&self, next: &Vertex) -> Color {
+        match (&self.rec, self.kind) {
+            (Some(rec), VertexKind::Surface | VertexKind::Medium) => {
+                let scattered = self.r_in.continued(self.p, next.p - self.p);
+                rec.mat.eval(self.r_in, rec, scattered)
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Radiance leaving the vertex towards `target`. Surface vertices can only
+    // be asked about the direction they were reached from.
+    fn emitted(&self, target: Point3) -> Color {
+        let Some(rec) = &self.rec else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+
+        if self.kind == VertexKind::Light {
+            rec.emitted_towards(target - self.p, self.time())
+        } else {
+            rec.mat.emitted(self.r_in, rec, rec.u, rec.v, rec.p)
+        }
+    }
+
+    // Converts a solid angle density at this vertex into an area density at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let dist_squared = w.length_squared();
+        if dist_squared == 0.0 {
+            return 0.0;
+        }
+
+        let cosine = if next.on_surface() {
+            (vec3::dot(next.n, w) / dist_squared.sqrt()).abs()
+        } else {
+            1.0
+        };
+
+        pdf * cosine / dist_squared
+    }
+
+    // Area density of sampling `next` from this vertex, having arrived from `prev`.
+    fn pdf(&self, scene: &Scene, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.p - self.p;
+
+        let pdf = match (self.kind, &self.rec, prev) {
+            (VertexKind::Light, _, _) => return self.pdf_light(next),
+            (VertexKind::Camera, _, _) => scene.cam.pdf_importance(self.p, direction).1,
+            (_, Some(rec), Some(prev)) => {
+                let r_in = Ray::new(prev.p, self.p - prev.p, self.time());
+                let scattered = Ray::new(self.p, direction, self.time());
+                rec.mat.scattering_pdf(r_in, rec, scattered)
+            }
+            _ => 0.0,
+        };
+
+        self.convert_density(pdf, next)
+    }
+
+    // Area density at `next` of an emission direction sampled from this
+    // vertex, which is treated as a point on a light.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let Some(rec) = &self.rec else {
+            return 0.0;
+        };
+
+        let w = next.p - self.p;
+        let outward = if rec.front_face { self.n } else { -self.n };
+        let pdf_dir = EmissionPdf::new(outward, rec.mat.emission_sides()).value(w);
+
+        self.convert_density(pdf_dir, next)
+    }
+
+    // Area density with which the light subpath would have started here.
+    fn pdf_light_origin(&self, scene: &Scene, from: &Vertex) -> f64 {
+        scene.lights.area_pdf_value(from.p, self.p - from.p)
+    }
+}
+
+pub fn render(cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable) -> Vec<Color> {
+    let scene = Scene { cam, world, lights };
+    let width = cam.image_width();
+    let splats: Vec<Mutex<Color>> = (0..width * cam.image_height())
+        .map(|_| Mutex::new(Color::new(0.0, 0.0, 0.0)))
+        .collect();
+
+    let mut pixels = cam.render_pixels(|i, j| {
+        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+        for s_j in 0..cam.sqrt_spp() {
+            for s_i in 0..cam.sqrt_spp() {
+                let r = cam.get_ray(i, j, s_i, s_j);
+
+                for (contribution, raster) in li(&scene, r) {
+                    match raster {
+                        Some((x, y)) => {
+                            *splats[(y * width + x) as usize].lock().unwrap() += contribution
+                        }
+                        None => pixel_color += contribution,
+                    }
+                }
+            }
+        }
+
+        pixel_color
+    });
+
+    // One light subpath was traced per camera sample, so splats share the
+    // same normalization as the per-pixel estimates.
+    for (pixel, splat) in pixels.iter_mut().zip(splats) {
+        *pixel = cam.pixel_samples_scale() * (*pixel + splat.into_inner().unwrap());
+    }
+
+    pixels
+}
+
+// Contributions of every connection strategy for one camera ray. Strategies
+// that connect to the camera carry the pixel they splat to.
+fn li(scene: &Scene, r: Ray) -> Vec<(Color, Option<(i32, i32)>)> {
+    let max_depth = scene.cam.max_depth();
+    let (camera_path, escape) = camera_subpath(scene, r, max_depth + 2);
+    let light_path = light_subpath(scene, r.time(), max_depth + 1);
+    let mut contributions = Vec::new();
+
+    for t in 1..=camera_path.len() {
+        for s in 0..=light_path.len() {
+            let depth = (s + t) as i32 - 2;
+            if (s == 1 && t == 1) || depth < 0 || depth > max_depth {
+                continue;
+            }
+
+            let (contribution, raster) = connect(scene, &light_path, &camera_path, s, t);
+            if !is_black(contribution) {
+                contributions.push((contribution, raster));
+            }
+        }
+    }
+
+    // Lights without geometry start no light subpaths and are never hit,
+    // which leaves next event estimation as the one strategy for their paths.
+    // Environment lights can also be reached by escaping the scene.
+    for pt in camera_path.iter().skip(1).take(max_depth as usize) {
+        if let (true, Some(rec)) = (pt.connectible, &pt.rec) {
+            let direct = light::direct_lighting(scene.world, scene.lights, pt.r_in, rec)
+                + environment_lighting(scene, pt.r_in, rec);
+            if !is_black(direct) {
+                contributions.push((pt.beta * direct, None));
+            }
+        }
+    }
+
+    if let (Some(escape), Some(last)) = (escape, camera_path.last()) {
+        let escaped = escaped_radiance(scene, last, &escape);
+        if !is_black(escaped) {
+            contributions.push((escaped, None));
+        }
+    }
+
+    contributions
+}
+
+// Light from the environment sampled at a camera vertex, weighted by the
+// balance heuristic against the camera subpath escaping towards it.
+fn environment_lighting(scene: &Scene, r_in: Ray, rec: &HitRecord) -> Color {
+    light::environment_lights(scene.lights.light_sources())
+        .filter_map(|env| {
+            light::sample_visible(scene.world, env, rec.p, r_in.time()).map(|s| (env, s))
+        })
+        .fold(Color::new(0.0, 0.0, 0.0), |sum, (env, sample)| {
+            let towards_light = r_in.continued(rec.p, sample.direction);
+            let pdf_light = env.pdf_li(sample.direction);
+            let pdf_bsdf = rec.mat.scattering_pdf(r_in, rec, towards_light);
+            let weight = pdf_light / (pdf_light + pdf_bsdf);
+
+            sum + weight * rec.mat.eval(r_in, rec, towards_light) * sample.radiance
+        })
+}
+
+// Radiance from the environment along the ray that ended the camera subpath,
+// weighted against sampling it from the vertex the ray left where that was
+// possible, plus the camera's background, which nothing samples.
+fn escaped_radiance(scene: &Scene, last: &Vertex, escape: &Escape) -> Color {
+    let camera_ray = last.kind == VertexKind::Camera;
+    let sampled_there = !camera_ray && last.connectible;
+    let direction = escape.r.direction();
+
+    let environment = light::environment_lights(scene.lights.light_sources()).fold(
+        Color::new(0.0, 0.0, 0.0),
+        |sum, env| {
+            let weight = if sampled_there {
+                escape.pdf / (escape.pdf + env.pdf_li(direction))
+            } else {
+                1.0
+            };
+
+            sum + weight * env.le(direction)
+        },
+    );
+
+    escape.beta * (environment + scene.cam.background(direction, camera_ray))
+}
+
+fn camera_subpath<'a>(
+    scene: &Scene<'a>,
+    r: Ray,
+    max_vertices: i32,
+) -> (Vec<Vertex<'a>>, Option<Escape>) {
+    let beta = Color::new(1.0, 1.0, 1.0);
+    let (_, pdf_dir) = scene.cam.pdf_importance(r.origin(), r.direction());
+    let mut path = vec![Vertex::camera(
+        r.origin(),
+        scene.cam.normal(),
+        beta,
+        r.time(),
+    )];
+
+    let escape = random_walk(scene.world, r, beta, pdf_dir, max_vertices - 1, &mut path);
+
+    (path, escape)
+}
+
+fn light_subpath<'a>(scene: &Scene<'a>, time: f64, max_vertices: i32) -> Vec<Vertex<'a>> {
+    let Some((rec, pdf_pos)) = scene.lights.random_on_surface() else {
+        return vec![];
+    };
+
+    let emission = EmissionPdf::new(rec.normal, rec.mat.emission_sides());
+    let Some(direction) = emission.generate() else {
+        return vec![];
+    };
+    let pdf_dir = emission.value(direction);
+    if pdf_pos <= 0.0 || pdf_dir <= 0.0 {
+        return vec![];
+    }
+
+    let cosine = vec3::dot(rec.normal, vec3::unit_vector(direction)).abs();
+    let p = rec.p;
+    let light = Vertex::light(rec, Color::new(0.0, 0.0, 0.0), pdf_pos, time);
+    let le = light.emitted(p + direction);
+    if is_black(le) {
+        return vec![];
+    }
+
+    let beta = le * cosine / (pdf_pos * pdf_dir);
+    let mut path = vec![Vertex { beta: le, ..light }];
+
+    random_walk(
+        scene.world,
+        Ray::new(p, direction, time),
+        beta,
+        pdf_dir,
+        max_vertices - 1,
+        &mut path,
+    );
+
+    path
+}
+
+// A ray that left the scene at the end of a walk, with its throughput and the
+// solid angle density its direction was sampled with.
+struct Escape {
+    r: Ray,
+    beta: Color,
+    pdf: f64,
+}
+
+// Extends `path` by up to `max_vertices` scattering vertices, filling in the
+// forward and reverse area densities as it goes.
+fn random_walk<'a>(
+    world: &'a dyn Hittable,
+    mut r: Ray,
+    mut beta: Color,
+    pdf: f64,
+    max_vertices: i32,
+    path: &mut Vec<Vertex<'a>>,
+) -> Option<Escape> {
+    if max_vertices <= 0 {
+        return None;
+    }
+
+    let mut pdf_fwd = pdf;
+    let mut bounces = 0;
+
+    loop {
+        let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+            return Some(Escape {
+                r,
+                beta,
+                pdf: pdf_fwd,
+            });
+        };
+
+        // The medium crossed on the way absorbs part of the throughput.
+        beta = beta * r.transmittance(rec.t);
+
+        let mut vertex = Vertex::surface(rec, r, beta);
+        vertex.pdf_fwd = path.last().unwrap().convert_density(pdf_fwd, &vertex);
+        let next = vertex.scatter();
+        path.push(vertex);
+
+        bounces += 1;
+        if bounces >= max_vertices {
+            return None;
+        }
+
+        let (scattered, weight, fwd, rev) = next?;
+
+        beta = beta * weight;
+        pdf_fwd = fwd;
+
+        let n = path.len();
+        path[n - 2].pdf_rev = path[n - 1].convert_density(rev, &path[n - 2]);
+        r = scattered;
+    }
+}
+
+fn connect(
+    scene: &Scene,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+) -> (Color, Option<(i32, i32)>) {
+    let black = (Color::new(0.0, 0.0, 0.0), None);
+    let pt = &camera_path[t - 1];
+    let mut sampled: Option<Vertex> = None;
+    let mut raster = None;
+
+    let contribution = if s == 0 {
+        // The camera subpath hit an emitter on its own.
+        pt.beta * pt.emitted(camera_path[t - 2].p)
+    } else if t == 1 {
+        // Connect a light subpath vertex directly to the lens.
+        let qs = &light_path[s - 1];
+        if !qs.connectible {
+            return black;
+        }
+
+        let Some((lens_point, pdf, we, i, j)) = scene.cam.sample_importance(qs.p) else {
+            return black;
+        };
+        if pdf <= 0.0 || we <= 0.0 || !unoccluded(scene.world, qs.p, lens_point, qs.time()) {
+            return black;
+        }
+
+        let beta = Color::new(1.0, 1.0, 1.0) * (we / pdf);
+        let camera = Vertex::camera(lens_point, scene.cam.normal(), beta, qs.time());
+        let contribution = qs.beta * qs.f(&camera) * camera.beta;
+
+        raster = Some((i, j));
+        sampled = Some(camera);
+        contribution
+    } else if s == 1 {
+        // Next event estimation through the lights' own sampling strategy.
+        if !pt.connectible {
+            return black;
+        }
+
+        let direction = scene.lights.random(pt.p);
+        let pdf = scene.lights.pdf_value(pt.p, direction);
+        let light_ray = Ray::new(pt.p, direction, pt.time());
+        let Some(rec) = scene
+            .lights
+            .hit(&light_ray, Interval::new(0.001, f64::INFINITY))
+        else {
+            return black;
+        };
+        if pdf <= 0.0 || !unoccluded(scene.world, pt.p, rec.p, pt.time()) {
+            return black;
+        }
+
+        let light = Vertex::light(outward(rec), Color::new(0.0, 0.0, 0.0), 0.0, pt.time());
+        let pdf_fwd = light.pdf_light_origin(scene, pt);
+        let light = Vertex {
+            beta: light.emitted(pt.p) / pdf,
+            pdf_fwd,
+            ..light
+        };
+        let contribution = pt.beta * pt.f(&light) * light.beta;
+
+        sampled = Some(light);
+        contribution
+    } else {
+        let qs = &light_path[s - 1];
+        if !qs.connectible || !pt.connectible {
+            return black;
+        }
+
+        let contribution = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta;
+        if is_black(contribution) || !unoccluded(scene.world, qs.p, pt.p, pt.time()) {
+            return black;
+        }
+
+        contribution / (qs.p - pt.p).length_squared()
+    };
+
+    if is_black(contribution) {
+        return black;
+    }
+
+    let weight = mis_weight(scene, light_path, camera_path, sampled.as_ref(), s, t);
+
+    (weight * contribution, raster)
+}
+
+// Balance heuristic weight of strategy (s, t), computed from ratios of the
+// densities with which the other strategies would have produced the same path.
+fn mis_weight(
+    scene: &Scene,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let mut cam: Vec<&Vertex> = camera_path[..t].iter().collect();
+    let mut light: Vec<&Vertex> = light_path[..s].iter().collect();
+    if let Some(v) = sampled {
+        if s == 1 {
+            light[0] = v;
+        } else if t == 1 {
+            cam[0] = v;
+        }
+    }
+
+    // (pdf_fwd, pdf_rev, delta) per vertex; the reverse densities around the
+    // connection depend on the strategy, so they are patched on these copies.
+    let mut cam_pdfs: Vec<(f64, f64, bool)> = cam
+        .iter()
+        .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+        .collect();
+    let mut light_pdfs: Vec<(f64, f64, bool)> = light
+        .iter()
+        .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+        .collect();
+
+    let pt = cam[t - 1];
+    let pt_minus = if t > 1 { Some(cam[t - 2]) } else { None };
+    let qs = if s > 0 { Some(light[s - 1]) } else { None };
+    let qs_minus = if s > 1 { Some(light[s - 2]) } else { None };
+
+    cam_pdfs[t - 1].1 = match (qs, pt_minus) {
+        (Some(qs), _) => qs.pdf(scene, qs_minus, pt),
+        (None, Some(pt_minus)) => pt.pdf_light_origin(scene, pt_minus),
+        (None, None) => 0.0,
+    };
+
+    if let Some(pt_minus) = pt_minus {
+        cam_pdfs[t - 2].1 = match qs {
+            Some(qs) => pt.pdf(scene, Some(qs), pt_minus),
+            None => pt.pdf_light(pt_minus),
+        };
+    }
+
+    if let Some(qs) = qs {
+        light_pdfs[s - 1].1 = pt.pdf(scene, pt_minus, qs);
+    }
+
+    if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+        light_pdfs[s - 2].1 = qs.pdf(scene, Some(pt), qs_minus);
+    }
+
+    let remap0 = |f: f64| if f != 0.0 { f } else { 1.0 };
+    let mut sum_ri = 0.0;
+
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap0(cam_pdfs[i].1) / remap0(cam_pdfs[i].0);
+        if !cam_pdfs[i].2 && !cam_pdfs[i - 1].2 {
+            sum_ri += ri;
+        }
+    }
+
+    let mut ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap0(light_pdfs[i].1) / remap0(light_pdfs[i].0);
+        let delta_light_vertex = i > 0 && light_pdfs[i - 1].2;
+        if !light_pdfs[i].2 && !delta_light_vertex {
+            sum_ri += ri;
+        }
+    }
+
+    1.0 / (1.0 + sum_ri)
+}
+
+fn outward(rec: HitRecord) -> HitRecord {
+    let normal = if rec.front_face {
+        rec.normal
+    } else {
+        -rec.normal
+    };
+
+    HitRecord {
+        normal,
+        front_face: true,
+        ..rec
+    }
+}
+
+fn unoccluded(world: &dyn Hittable, p0: Point3, p1: Point3, time: f64) -> bool {
+    let direction = p1 - p0;
+    let distance = direction.length();
+    let r = Ray::new(p0, direction / distance, time);
+
+    world
+        .hit(&r, Interval::new(0.001, distance - 0.001))
+        .is_none()
+}
+
+fn is_black(c: Color) -> bool {
+    c.x() <= 0.0 && c.y() <= 0.0 && c.z() <= 0.0
+}
@@ -1,11 +1,12 @@
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{self, HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
+use std::sync::Arc;
 
 pub enum BVHNode {
     Leaf {
-        object: Box<dyn Hittable>,
+        object: Arc<dyn Hittable>,
         bbox: AABB,
     },
     Internal {
@@ -20,7 +21,7 @@ impl BVHNode {
         Self::node(list.objects)
     }
 
-    pub fn node(mut objects: Vec<Box<dyn Hittable>>) -> Self {
+    pub fn node(mut objects: Vec<Arc<dyn Hittable>>) -> Self {
         let mut bbox = AABB::empty();
         for object in objects.iter() {
             bbox = AABB::from_aabb(bbox, object.bounding_box());
@@ -111,4 +112,22 @@ impl Hittable for BVHNode {
             BVHNode::Internal { bbox, .. } => *bbox,
         }
     }
+
+    fn power(&self) -> f64 {
+        match self {
+            BVHNode::Leaf { object, .. } => object.power(),
+            BVHNode::Internal { left, right, .. } => left.power() + right.power(),
+        }
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        match self {
+            BVHNode::Leaf { object, .. } => hittable::emitters_of(object),
+            BVHNode::Internal { left, right, .. } => {
+                let mut emitters = left.emitters();
+                emitters.extend(right.emitters());
+                emitters
+            }
+        }
+    }
 }
@@ -1,15 +1,23 @@
 use core::f64;
-use rand::Rng;
 use rayon::prelude::*;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
+use crate::background::Background;
+use crate::bdpt;
 use crate::color::{self, Color};
-use crate::hittable::Hittable;
+use crate::guiding;
+use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::light;
 use crate::material::ScatterRecord;
+use crate::mlt;
 use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
+use crate::photon_map;
 use crate::ray::Ray;
+use crate::sampler;
+use crate::spectral;
 use crate::vec3::{self, Point3, Vec3};
 
 pub struct Camera {
@@ -24,13 +32,41 @@ pub struct Camera {
     sqrt_spp: i32,
     recip_sqrt_spp: f64,
     max_depth: i32,
-    background: Color,
+    // What camera rays that miss everything see, and what every other ray
+    // that escapes the scene sees.
+    background: Arc<dyn Background>,
+    lighting: Arc<dyn Background>,
     u: Vec3,
     v: Vec3,
     w: Vec3,
     defocus_angle: f64,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_dist: f64,
+}
+
+pub enum Integrator {
+    PathTracer,
+    Bdpt,
+    Guided {
+        training_passes: i32,
+    },
+    PhotonMap {
+        photons: usize,
+        nearest: usize,
+        radius: f64,
+    },
+    Sppm {
+        iterations: i32,
+        photons: usize,
+        radius: f64,
+    },
+    Mlt {
+        mutations_per_pixel: usize,
+        bootstrap_samples: usize,
+        chains: usize,
+    },
+    Spectral,
 }
 
 impl Camera {
@@ -111,62 +147,258 @@ impl Camera {
             sqrt_spp,
             recip_sqrt_spp,
             max_depth,
-            background,
+            background: Arc::new(background),
+            lighting: Arc::new(background),
             u,
             v,
             w,
             defocus_angle,
             defocus_disk_u,
             defocus_disk_v,
+            focus_dist,
+        }
+    }
+
+    // Sets the background that camera rays see and that lights the scene.
+    pub fn with_background(self, background: Box<dyn Background>) -> Self {
+        let background: Arc<dyn Background> = Arc::from(background);
+
+        Self {
+            lighting: background.clone(),
+            background,
+            ..self
+        }
+    }
+
+    // Sets only what camera rays see, such as a black backdrop behind a
+    // scene that the lighting background still lights.
+    pub fn with_visible_background(self, background: Box<dyn Background>) -> Self {
+        Self {
+            background: Arc::from(background),
+            ..self
+        }
+    }
+
+    // Sets only what rays that have bounced off the scene see.
+    pub fn with_lighting_background(self, lighting: Box<dyn Background>) -> Self {
+        Self {
+            lighting: Arc::from(lighting),
+            ..self
+        }
+    }
+
+    // The background seen along `direction` by a ray leaving the scene,
+    // straight from the camera or not.
+    pub fn background(&self, direction: Vec3, camera_ray: bool) -> Color {
+        if camera_ray {
+            self.background.value(direction)
+        } else {
+            self.lighting.value(direction)
         }
     }
 
     pub fn render(&self, world: &dyn Hittable, lights: &dyn Hittable) {
+        self.render_with(Integrator::PathTracer, world, lights);
+    }
+
+    pub fn render_with(&self, integrator: Integrator, world: &dyn Hittable, lights: &dyn Hittable) {
         let start = Instant::now();
-        let completed = AtomicUsize::new(0);
-        let total_lines = self.image_height as usize;
+
+        let pixels = match integrator {
+            Integrator::PathTracer => self.render_pixels(|i, j| {
+                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+                for s_j in 0..self.sqrt_spp {
+                    for s_i in 0..self.sqrt_spp {
+                        let r = self.get_ray(i, j, s_i, s_j);
+                        pixel_color += ray_color(self, r, self.max_depth, world, lights);
+                    }
+                }
+
+                self.pixel_samples_scale * pixel_color
+            }),
+            Integrator::Bdpt => bdpt::render(self, world, lights),
+            Integrator::Guided { training_passes } => {
+                guiding::render(self, world, lights, training_passes)
+            }
+            Integrator::PhotonMap {
+                photons,
+                nearest,
+                radius,
+            } => photon_map::render(self, world, lights, photons, nearest, radius),
+            Integrator::Sppm {
+                iterations,
+                photons,
+                radius,
+            } => photon_map::render_progressive(self, world, lights, iterations, photons, radius),
+            Integrator::Mlt {
+                mutations_per_pixel,
+                bootstrap_samples,
+                chains,
+            } => mlt::render(
+                self,
+                world,
+                lights,
+                mutations_per_pixel,
+                bootstrap_samples,
+                chains,
+            ),
+            Integrator::Spectral => spectral::render(self, world, lights),
+        };
+
         println!("P3");
         println!("{} {}", self.image_width, self.image_height);
         println!("255");
 
-        let pixels: Vec<Color> = (0..self.image_height)
-            .into_par_iter()
-            .flat_map_iter(|j| {
-                let row: Vec<Color> = (0..self.image_width as i32)
-                    .map(move |i| {
-                        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+        for pixel in pixels {
+            println!("{}", color::write_color(pixel));
+        }
 
-                        for s_j in 0..self.sqrt_spp {
-                            for s_i in 0..self.sqrt_spp {
-                                let r = self.get_ray(i, j, s_i, s_j);
-                                pixel_color += ray_color(r, self.max_depth, world, lights);
-                            }
-                        }
+        let duration = start.elapsed();
+        eprintln!("Done.");
+        eprintln!("Render time: {:?}", duration);
+    }
 
-                        self.pixel_samples_scale * pixel_color
-                    })
-                    .collect();
+    /// Evaluates `pixel` for every pixel in parallel, one row per task, and
+    /// returns the colors in scanline order.
+    pub fn render_pixels<F>(&self, pixel: F) -> Vec<Color>
+    where
+        F: Fn(i32, i32) -> Color + Sync,
+    {
+        let completed = AtomicUsize::new(0);
+        let total_lines = self.image_height as usize;
+
+        (0..self.image_height)
+            .into_par_iter()
+            .flat_map_iter(|j| {
+                let row: Vec<Color> = (0..self.image_width as i32).map(|i| pixel(i, j)).collect();
 
                 let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
                 eprintln!("Progress: {:.1}%", 100.0 * done as f64 / total_lines as f64);
                 row
             })
-            .collect();
+            .collect()
+    }
 
-        for pixel in pixels {
-            println!("{}", color::write_color(pixel));
+    pub fn image_width(&self) -> i32 {
+        self.image_width as i32
+    }
+
+    pub fn image_height(&self) -> i32 {
+        self.image_height
+    }
+
+    pub fn sqrt_spp(&self) -> i32 {
+        self.sqrt_spp
+    }
+
+    pub fn pixel_samples_scale(&self) -> f64 {
+        self.pixel_samples_scale
+    }
+
+    pub fn max_depth(&self) -> i32 {
+        self.max_depth
+    }
+
+    /// Importance `We` of a camera ray leaving the lens at `origin` along
+    /// `direction`, with the pixel it lands in. `None` if the ray misses the film.
+    pub fn importance(&self, origin: Point3, direction: Vec3) -> Option<(f64, i32, i32)> {
+        let (i, j) = self.raster_position(origin, direction)?;
+        let cos_theta = vec3::dot(vec3::unit_vector(direction), -self.w);
+        let cos2_theta = cos_theta * cos_theta;
+
+        Some((
+            1.0 / (self.film_area() * self.lens_area() * cos2_theta * cos2_theta),
+            i,
+            j,
+        ))
+    }
+
+    /// Positional (area) and directional (solid angle) densities with which
+    /// `get_ray` would generate a ray leaving `origin` along `direction`.
+    pub fn pdf_importance(&self, origin: Point3, direction: Vec3) -> (f64, f64) {
+        if self.raster_position(origin, direction).is_none() {
+            return (0.0, 0.0);
         }
 
-        let duration = start.elapsed();
-        eprintln!("Done.");
-        eprintln!("Render time: {:?}", duration);
+        let cos_theta = vec3::dot(vec3::unit_vector(direction), -self.w);
+
+        (
+            1.0 / self.lens_area(),
+            1.0 / (self.film_area() * cos_theta * cos_theta * cos_theta),
+        )
+    }
+
+    /// Samples a point on the lens as seen from `p`. Returns the lens point,
+    /// the solid angle pdf of that choice at `p`, the importance carried back
+    /// along it and the pixel it lands in.
+    pub fn sample_importance(&self, p: Point3) -> Option<(Point3, f64, f64, i32, i32)> {
+        let lens_point = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample()
+        };
+
+        let direction = p - lens_point;
+        let (we, i, j) = self.importance(lens_point, direction)?;
+        let cos_theta = vec3::dot(vec3::unit_vector(direction), -self.w);
+        let pdf = direction.length_squared() / (cos_theta * self.lens_area());
+
+        Some((lens_point, pdf, we, i, j))
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        -self.w
+    }
+
+    fn raster_position(&self, origin: Point3, direction: Vec3) -> Option<(i32, i32)> {
+        let direction = vec3::unit_vector(direction);
+        let cos_theta = vec3::dot(direction, -self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Follow the ray to the plane of focus and express the point in
+        // pixel units relative to the upper-left corner of the viewport.
+        let focus = origin + (self.focus_dist / cos_theta) * direction;
+        let offset = focus - (self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v));
+        let x = vec3::dot(offset, self.pixel_delta_u) / self.pixel_delta_u.length_squared();
+        let y = vec3::dot(offset, self.pixel_delta_v) / self.pixel_delta_v.length_squared();
+
+        if x < 0.0 || y < 0.0 || x >= self.image_width || y >= self.image_height as f64 {
+            return None;
+        }
+
+        Some((x as i32, y as i32))
     }
 
-    fn get_ray(&self, i: i32, j: i32, s_i: i32, s_j: i32) -> Ray {
+    // Area of the viewport scaled back to unit distance from the camera.
+    fn film_area(&self) -> f64 {
+        let width = self.pixel_delta_u.length() * self.image_width;
+        let height = self.pixel_delta_v.length() * self.image_height as f64;
+
+        width * height / (self.focus_dist * self.focus_dist)
+    }
+
+    fn lens_area(&self) -> f64 {
+        if self.defocus_angle <= 0.0 {
+            1.0
+        } else {
+            f64::consts::PI * self.defocus_disk_u.length_squared()
+        }
+    }
+
+    pub fn get_ray(&self, i: i32, j: i32, s_i: i32, s_j: i32) -> Ray {
         let offset = self.sample_square_stratified(s_i, s_j);
-        let pixel_sample = self.pixel00_loc
-            + ((i as f64 + offset.x()) * self.pixel_delta_u)
-            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+
+        self.get_ray_at(i as f64 + 0.5 + offset.x(), j as f64 + 0.5 + offset.y())
+    }
+
+    // Camera ray through continuous raster coordinates, where pixel (i, j)
+    // covers [i, i + 1) x [j, j + 1).
+    pub fn get_ray_at(&self, x: f64, y: f64) -> Ray {
+        let pixel_sample =
+            self.pixel00_loc + ((x - 0.5) * self.pixel_delta_u) + ((y - 0.5) * self.pixel_delta_v);
 
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
@@ -175,7 +407,7 @@ impl Camera {
         };
 
         let ray_direction = pixel_sample - ray_origin;
-        let ray_time = rand::rng().random();
+        let ray_time = sampler::random_double();
 
         Ray::new(ray_origin, ray_direction, ray_time)
     }
@@ -187,8 +419,8 @@ impl Camera {
     }
 
     fn sample_square_stratified(&self, s_i: i32, s_j: i32) -> Vec3 {
-        let px: f64 = (s_i as f64 + rand::rng().random::<f64>()) * self.recip_sqrt_spp - 0.5;
-        let py: f64 = (s_j as f64 + rand::rng().random::<f64>()) * self.recip_sqrt_spp - 0.5;
+        let px: f64 = (s_i as f64 + sampler::random_double()) * self.recip_sqrt_spp - 0.5;
+        let py: f64 = (s_j as f64 + sampler::random_double()) * self.recip_sqrt_spp - 0.5;
 
         Vec3::new(px, py, 0.0)
     }
@@ -199,48 +431,94 @@ fn degrees_to_radians(degrees: f64) -> f64 {
 }
 
 fn sample_square() -> Vec3 {
-    let x: f64 = rand::rng().random();
-    let y: f64 = rand::rng().random();
+    let x = sampler::random_double();
+    let y = sampler::random_double();
 
     Vec3::new(x - 0.5, y - 0.5, 0.0)
 }
 
-fn ray_color(r: Ray, depth: i32, world: &dyn Hittable, lights: &dyn Hittable) -> Color {
+pub fn ray_color(
+    cam: &Camera,
+    r: Ray,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+) -> Color {
     if depth <= 0 {
         return Color::new(0.0, 0.0, 0.0);
     }
 
-    if let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) {
-        let color_from_emission = rec.mat.emitted(r, &rec, rec.u, rec.v, rec.p);
+    let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+        let camera_ray = depth == cam.max_depth();
+        return cam.background(r.direction(), camera_ray)
+            + light::environment(lights, r.direction());
+    };
 
-        if let Some(srec) = rec.mat.scatter(r, &rec) {
-            match srec {
-                ScatterRecord::SkipPdf { attenuation, ray } => {
-                    attenuation * ray_color(ray, depth - 1, world, lights)
-                }
-                ScatterRecord::Pdf {
-                    attenuation,
-                    pdf_ptr,
-                } => {
-                    let light_pdf = HittablePdf::new(lights, rec.p);
-                    let p = MixturePdf::new(&light_pdf, pdf_ptr);
-                    let scattered = Ray::new(rec.p, p.generate(), r.time());
-                    let pdf_value = p.value(scattered.direction());
-
-                    let scattering_pdf = rec.mat.scattering_pdf(r, &rec, scattered);
-
-                    let sample_color = ray_color(scattered, depth - 1, world, lights);
-                    let color_from_scatter =
-                        (attenuation * scattering_pdf * sample_color) / pdf_value;
-                    let color_from_scatter = color_from_scatter.clamp(0.0, 30.0);
-
-                    color_from_emission + color_from_scatter
-                }
+    // The medium crossed on the way absorbs part of whatever the ray finds.
+    r.transmittance(rec.t) * hit_color(cam, r, &rec, depth, world, lights)
+}
+
+// Light leaving `rec` back along `r`.
+fn hit_color(
+    cam: &Camera,
+    r: Ray,
+    rec: &HitRecord,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+) -> Color {
+    let color_from_emission = rec.mat.emitted(r, rec, rec.u, rec.v, rec.p);
+
+    match rec.mat.scatter(r, rec) {
+        None => color_from_emission,
+        Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+            attenuation * ray_color(cam, *ray, depth - 1, world, lights)
+        }
+        Some(ScatterRecord::Pdf { pdf_ptr, .. }) => {
+            let light_pdf = HittablePdf::new(lights, rec.p);
+            let p = light_mixture(&light_pdf, lights, pdf_ptr, r);
+            let color_from_sources = light::direct_lighting(world, lights, r, rec);
+
+            // A direction nothing can scatter into ends the path.
+            let scattered = match p.generate() {
+                Some(direction) => rec.mat.scattered_ray(r, rec, direction),
+                None => return color_from_emission + color_from_sources,
+            };
+            let pdf_value = p.value(scattered.direction());
+            if pdf_value <= 0.0 {
+                return color_from_emission + color_from_sources;
             }
-        } else {
-            color_from_emission
+
+            let f = rec.mat.eval(r, rec, scattered);
+
+            let sample_color = ray_color(cam, scattered, depth - 1, world, lights);
+            let color_from_scatter = (f * sample_color) / pdf_value;
+            let color_from_scatter = color_from_scatter.clamp(0.0, 30.0);
+
+            color_from_emission + color_from_sources + color_from_scatter
         }
+    }
+}
+
+// The BSDF's sampling strategy mixed with the light list's, unless the list
+// has nothing to sample: no geometry or environment, only lights that rays
+// can't reach. Nor can rays inside a dielectric, such as a subsurface random
+// walk, which refract on their way out rather than heading straight for a
+// light.
+pub fn light_mixture<'a>(
+    light_pdf: &'a HittablePdf<'a>,
+    lights: &dyn Hittable,
+    bsdf_pdf: Box<dyn Pdf + 'a>,
+    r_in: Ray,
+) -> Box<dyn Pdf + 'a> {
+    let has_environment = light::environment_lights(lights.light_sources())
+        .next()
+        .is_some();
+    let unreachable = lights.bounding_box().is_empty() && !has_environment;
+
+    if unreachable || r_in.medium().is_some() {
+        bsdf_pdf
     } else {
-        Color::new(0.0, 0.0, 0.0)
+        Box::new(MixturePdf::new(light_pdf, bsdf_pdf))
     }
 }
@@ -23,6 +23,10 @@ pub fn write_color(pixel_color: Color) -> String {
     format!("{} {} {}", rbyte, gbyte, bbyte)
 }
 
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
 fn linear_to_gamma(linear_component: f64) -> f64 {
     if linear_component > 0.0 {
         return linear_component.sqrt();
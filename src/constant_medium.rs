@@ -5,9 +5,9 @@ use crate::hittable::Hittable;
 use crate::interval::Interval;
 use crate::material::{Isotropic, Material};
 use crate::ray::Ray;
+use crate::sampler;
 use crate::texture::Texture;
 use crate::vec3::Vec3;
-use rand::Rng;
 
 pub struct ConstantMedium {
     boundary: Box<dyn Hittable>,
@@ -67,7 +67,7 @@ impl Hittable for ConstantMedium {
 
         let ray_length = r.direction().length();
         let distance_inside_boundary = (t2 - t1) * ray_length;
-        let hit_distance: f64 = self.neg_inv_density * rand::rng().random::<f64>().ln();
+        let hit_distance: f64 = self.neg_inv_density * sampler::random_double().ln();
 
         if hit_distance > distance_inside_boundary {
             return None;
@@ -0,0 +1,163 @@
+// Piecewise-constant densities over [0, 1) and [0, 1)^2, sampled by inverting
+// their CDFs so that nearby random numbers map to nearby points.
+
+pub struct PiecewiseConstant1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl PiecewiseConstant1D {
+    // `func` gives the function's value on each of its equal-width pieces.
+    // Negative values count as their magnitude.
+    pub fn new(func: &[f64]) -> Self {
+        let func: Vec<f64> = func.iter().map(|f| f.abs()).collect();
+        let n = func.len() as f64;
+
+        let mut cdf = vec![0.0; func.len() + 1];
+        for i in 0..func.len() {
+            cdf[i + 1] = cdf[i] + func[i] / n;
+        }
+
+        let integral = cdf[func.len()];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n
+            };
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    // Maps `u` to a point, returning it with its density and the piece it
+    // fell in. The density is zero if the function is.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.len() - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+
+        let x = ((offset as f64 + du) / self.len() as f64).min(1.0 - f64::EPSILON);
+
+        (x, self.pdf_of(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        self.pdf_of(self.offset(x))
+    }
+
+    fn offset(&self, x: f64) -> usize {
+        ((x * self.len() as f64).max(0.0) as usize).min(self.len() - 1)
+    }
+
+    fn pdf_of(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            0.0
+        }
+    }
+}
+
+// A density over (u, v) sampled by picking v from the marginal density of
+// the rows and then u from that row.
+pub struct PiecewiseConstant2D {
+    conditional: Vec<PiecewiseConstant1D>,
+    marginal: PiecewiseConstant1D,
+}
+
+impl PiecewiseConstant2D {
+    // `func` holds `nv` rows of `nu` values each, v = 0 first.
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Self {
+        let conditional: Vec<PiecewiseConstant1D> = func
+            .chunks(nu)
+            .take(nv)
+            .map(PiecewiseConstant1D::new)
+            .collect();
+        let row_integrals: Vec<f64> = conditional.iter().map(|row| row.integral()).collect();
+
+        Self {
+            conditional,
+            marginal: PiecewiseConstant1D::new(&row_integrals),
+        }
+    }
+
+    // The function's integral over the unit square.
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    pub fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (y, pdf_v, row) = self.marginal.sample(v);
+        let (x, pdf_u, _) = self.conditional[row].sample(u);
+
+        ((x, y), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = self.marginal.offset(v);
+
+        self.conditional[row].pdf(u) * self.marginal.pdf_of(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler;
+
+    #[test]
+    fn sampling_2d_matches_pdf() {
+        let (nu, nv) = (4, 3);
+        let func = [
+            1.0, 2.0, 0.0, 4.0, //
+            0.5, 0.5, 3.0, 1.0, //
+            0.0, 6.0, 2.0, 0.25,
+        ];
+        let distribution = PiecewiseConstant2D::new(&func, nu, nv);
+        let integral: f64 = func.iter().sum::<f64>() / (nu * nv) as f64;
+        assert!((distribution.integral() - integral).abs() < 1e-12);
+
+        let n = 200_000;
+        let mut counts = [0; 12];
+        for _ in 0..n {
+            let ((x, y), pdf) =
+                distribution.sample(sampler::random_double(), sampler::random_double());
+            assert!((pdf - distribution.pdf(x, y)).abs() < 1e-9);
+
+            let cell = (y * nv as f64) as usize * nu + (x * nu as f64) as usize;
+            counts[cell] += 1;
+        }
+
+        for (cell, &f) in func.iter().enumerate() {
+            let (i, j) = (cell % nu, cell / nu);
+            let (x, y) = ((i as f64 + 0.5) / nu as f64, (j as f64 + 0.5) / nv as f64);
+            let expected = f / integral / (nu * nv) as f64;
+            assert!((distribution.pdf(x, y) - f / integral).abs() < 1e-9);
+
+            let frequency = counts[cell] as f64 / n as f64;
+            assert!(
+                (frequency - expected).abs() < 0.005,
+                "cell {cell}: frequency {frequency}, expected {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use core::f64;
+
+use crate::aabb::AABB;
+use crate::color::{self, Color};
+use crate::distribution::PiecewiseConstant2D;
+use crate::light::{self, Light, LightSample};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
+
+// Image-based lighting: radiance arriving from every direction, read from an
+// equirectangular (latitude-longitude) image. The top row looks straight up
+// (+y), the bottom row straight down, and the middle column towards -z.
+// Directions are sampled in proportion to the luminance of their texel, with
+// each row weighed by its solid angle, so bright regions such as the sun
+// stand out and the poles don't get more samples than they should.
+
+pub struct EnvironmentLight {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+    distribution: PiecewiseConstant2D,
+    rotation: f64,
+    intensity: f64,
+}
+
+impl EnvironmentLight {
+    // Loads an HDR, EXR or any other image the `image` crate can read.
+    // Values are taken as linear radiance.
+    pub fn new(filename: &str) -> Self {
+        let image = image::open(filename)
+            .expect("Failed to load environment map")
+            .into_rgb32f();
+        let texels = image
+            .pixels()
+            .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+
+        Self::from_texels(image.width() as usize, image.height() as usize, texels)
+    }
+
+    // `texels` holds `height` rows of `width` values, top row first.
+    pub fn from_texels(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        let weights: Vec<f64> = texels
+            .iter()
+            .enumerate()
+            .map(|(index, &texel)| {
+                let theta = f64::consts::PI * ((index / width) as f64 + 0.5) / height as f64;
+                color::luminance(texel).max(0.0) * theta.sin()
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            distribution: PiecewiseConstant2D::new(&weights, width, height),
+            texels,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    // An environment sampled from `radiance`, a function of direction, at a
+    // resolution of `width` by `height` texels.
+    pub fn from_fn(width: usize, height: usize, radiance: impl Fn(Vec3) -> Color) -> Self {
+        let (du, dv) = (1.0 / width as f64, 1.0 / height as f64);
+        let texels = (0..height)
+            .flat_map(|j| (0..width).map(move |i| ((i as f64 + 0.5) * du, (j as f64 + 0.5) * dv)))
+            .map(|(u, v)| radiance(direction(u, v, 0.0)))
+            .collect();
+
+        Self::from_texels(width, height, texels)
+    }
+
+    // Turns the environment about the vertical axis.
+    pub fn with_rotation(self, degrees: f64) -> Self {
+        Self {
+            rotation: degrees.to_radians(),
+            ..self
+        }
+    }
+
+    // Scales the image's radiance.
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        Self { intensity, ..self }
+    }
+
+    fn texel(&self, u: f64, v: f64) -> Color {
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        self.intensity * self.texels[j * self.width + i]
+    }
+
+    // A direction sampled from the image, with its radiance and solid angle
+    // density.
+    fn sample(&self) -> Option<(Vec3, Color, f64)> {
+        let ((u, v), pdf_uv) = self
+            .distribution
+            .sample(sampler::random_double(), sampler::random_double());
+        let sin_theta = (f64::consts::PI * v).sin();
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+
+        let pdf = pdf_uv / (2.0 * f64::consts::PI * f64::consts::PI * sin_theta);
+
+        Some((direction(u, v, self.rotation), self.texel(u, v), pdf))
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, _p: Point3) -> Option<LightSample> {
+        let (direction, radiance, pdf) = self.sample()?;
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: radiance / pdf,
+        })
+    }
+
+    // Like a directional light, rays start on a disk facing the sampled
+    // direction just outside the scene's bounding sphere.
+    fn sample_le(&self, bounds: &AABB, time: f64) -> Option<(Ray, Color)> {
+        let (direction, radiance, pdf) = self.sample()?;
+        let (center, radius) = light::bounding_sphere(bounds);
+        let frame = Onb::new(direction);
+        let disk = vec3::random_in_unit_disk();
+        let origin = center + radius * (frame.w() + disk.x() * frame.u() + disk.y() * frame.v());
+        let disk_area = f64::consts::PI * radius * radius;
+
+        Some((
+            Ray::new(origin, -direction, time),
+            disk_area * radiance / pdf,
+        ))
+    }
+
+    fn power(&self, bounds: &AABB) -> f64 {
+        let (_, radius) = light::bounding_sphere(bounds);
+        let solid_angle_integral =
+            2.0 * f64::consts::PI * f64::consts::PI * self.distribution.integral();
+
+        f64::consts::PI * radius * radius * self.intensity * solid_angle_integral
+    }
+
+    fn is_environment(&self) -> bool {
+        true
+    }
+
+    fn le(&self, direction: Vec3) -> Color {
+        let (u, v) = uv(direction, self.rotation);
+
+        self.texel(u, v)
+    }
+
+    fn pdf_li(&self, direction: Vec3) -> f64 {
+        let (u, v) = uv(direction, self.rotation);
+        let sin_theta = (f64::consts::PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(u, v) / (2.0 * f64::consts::PI * f64::consts::PI * sin_theta)
+    }
+}
+
+// The direction at image coordinates (u, v), turned by `rotation` radians
+// about the vertical axis.
+fn direction(u: f64, v: f64, rotation: f64) -> Vec3 {
+    let theta = f64::consts::PI * v;
+    let phi = 2.0 * f64::consts::PI * u + rotation;
+
+    Vec3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        theta.sin() * phi.cos(),
+    )
+}
+
+// Image coordinates of `direction`, the inverse of `direction`: v runs from 0
+// straight up to 1 straight down.
+pub fn uv(direction: Vec3, rotation: f64) -> (f64, f64) {
+    let d = vec3::unit_vector(direction);
+    let theta = d.y().clamp(-1.0, 1.0).acos();
+    let phi = (d.x().atan2(d.z()) - rotation).rem_euclid(2.0 * f64::consts::PI);
+
+    (phi / (2.0 * f64::consts::PI), theta / f64::consts::PI)
+}
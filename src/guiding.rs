@@ -0,0 +1,337 @@
+use core::f64;
+use std::sync::Mutex;
+
+use crate::aabb::AABB;
+use crate::camera::{self, Camera};
+use crate::color::{self, Color};
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light;
+use crate::material::ScatterRecord;
+use crate::pdf::{HittablePdf, Pdf};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
+
+// Online path guiding: the scene's bounding box is cut into a grid of cells,
+// each holding a histogram of incident radiance over the sphere of
+// directions. Training passes with doubling sample counts fill the
+// histograms from the radiance their paths bring back; each pass samples
+// with what the previous one learned, so the estimate sharpens as it goes.
+// The final pass mixes the learned distribution with the BSDF's own pdf, so
+// directions the guide has not seen are still reachable.
+
+const SPATIAL_RESOLUTION: usize = 16;
+const DIRECTIONAL_RESOLUTION: usize = 16;
+const BINS: usize = DIRECTIONAL_RESOLUTION * DIRECTIONAL_RESOLUTION;
+const GUIDE_PROBABILITY: f64 = 0.5;
+
+// Directions are binned in the cylindrical (cos theta, phi) parametrisation,
+// which is area preserving, so every bin covers the same solid angle.
+fn direction_to_bin(direction: Vec3) -> usize {
+    let d = vec3::unit_vector(direction);
+    let u = 0.5 * (d.z().clamp(-1.0, 1.0) + 1.0);
+    let phi = d.y().atan2(d.x());
+    let v = (phi + f64::consts::PI) / (2.0 * f64::consts::PI);
+
+    let iu = ((u * DIRECTIONAL_RESOLUTION as f64) as usize).min(DIRECTIONAL_RESOLUTION - 1);
+    let iv = ((v * DIRECTIONAL_RESOLUTION as f64) as usize).min(DIRECTIONAL_RESOLUTION - 1);
+
+    iu * DIRECTIONAL_RESOLUTION + iv
+}
+
+fn bin_to_direction(bin: usize) -> Vec3 {
+    let iu = bin / DIRECTIONAL_RESOLUTION;
+    let iv = bin % DIRECTIONAL_RESOLUTION;
+
+    let z = -1.0 + 2.0 * (iu as f64 + sampler::random_double()) / DIRECTIONAL_RESOLUTION as f64;
+    let phi = -f64::consts::PI
+        + 2.0 * f64::consts::PI * (iv as f64 + sampler::random_double())
+            / DIRECTIONAL_RESOLUTION as f64;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+struct Grid {
+    bbox: AABB,
+}
+
+impl Grid {
+    fn cell(&self, p: Point3) -> usize {
+        let axis = |interval: Interval, x: f64| {
+            let t = (x - interval.min) / interval.size();
+            ((t * SPATIAL_RESOLUTION as f64).max(0.0) as usize).min(SPATIAL_RESOLUTION - 1)
+        };
+
+        let i = axis(self.bbox.x, p.x());
+        let j = axis(self.bbox.y, p.y());
+        let k = axis(self.bbox.z, p.z());
+
+        (i * SPATIAL_RESOLUTION + j) * SPATIAL_RESOLUTION + k
+    }
+}
+
+// Radiance collected during a training pass, per cell and direction bin.
+struct Recorder {
+    grid: Grid,
+    cells: Vec<Mutex<Vec<f64>>>,
+}
+
+impl Recorder {
+    fn new(grid: Grid) -> Self {
+        let cells = (0..SPATIAL_RESOLUTION.pow(3))
+            .map(|_| Mutex::new(vec![0.0; BINS]))
+            .collect();
+
+        Self { grid, cells }
+    }
+
+    // `weight` is the incident luminance divided by the pdf of the direction
+    // it arrived from, an unbiased estimate of the radiance integrated over
+    // the bin.
+    fn record(&self, p: Point3, direction: Vec3, weight: f64) {
+        if !weight.is_finite() || weight <= 0.0 {
+            return;
+        }
+
+        let cell = self.grid.cell(p);
+        self.cells[cell].lock().unwrap()[direction_to_bin(direction)] += weight;
+    }
+
+    fn into_field(self) -> GuidingField {
+        let cells = self
+            .cells
+            .into_iter()
+            .map(|cell| {
+                let histogram = cell.into_inner().unwrap();
+                let total: f64 = histogram.iter().sum();
+                if total <= 0.0 {
+                    return None;
+                }
+
+                // A floor keeps every bin reachable from the guide alone,
+                // which keeps the next pass from only confirming this one.
+                let floor = 0.01 * total / BINS as f64;
+                let total = total + floor * BINS as f64;
+                let cdf = histogram
+                    .iter()
+                    .scan(0.0, |sum, w| {
+                        *sum += (w + floor) / total;
+                        Some(*sum)
+                    })
+                    .collect();
+
+                Some(cdf)
+            })
+            .collect();
+
+        GuidingField {
+            grid: self.grid,
+            cells,
+        }
+    }
+}
+
+// The learned distributions, one cumulative histogram per trained cell.
+pub struct GuidingField {
+    grid: Grid,
+    cells: Vec<Option<Vec<f64>>>,
+}
+
+impl GuidingField {
+    pub fn pdf(&self, p: Point3) -> Option<GuidedPdf<'_>> {
+        let cdf = self.cells[self.grid.cell(p)].as_ref()?;
+
+        Some(GuidedPdf { cdf })
+    }
+}
+
+pub struct GuidedPdf<'a> {
+    cdf: &'a [f64],
+}
+
+impl Pdf for GuidedPdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        let bin = direction_to_bin(direction);
+        let below = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+
+        (self.cdf[bin] - below) * BINS as f64 / (4.0 * f64::consts::PI)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        let u = sampler::random_double() * self.cdf[BINS - 1];
+        let bin = self.cdf.partition_point(|&c| c < u).min(BINS - 1);
+
+        Some(bin_to_direction(bin))
+    }
+}
+
+// Picks the guide with `GUIDE_PROBABILITY`, otherwise the BSDF.
+struct GuidedBsdfPdf<'a> {
+    guide: GuidedPdf<'a>,
+    bsdf: Box<dyn Pdf>,
+}
+
+impl Pdf for GuidedBsdfPdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        GUIDE_PROBABILITY * self.guide.value(direction)
+            + (1.0 - GUIDE_PROBABILITY) * self.bsdf.value(direction)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        if sampler::random_double() < GUIDE_PROBABILITY {
+            self.guide.generate()
+        } else {
+            self.bsdf.generate()
+        }
+    }
+}
+
+pub fn render(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    training_passes: i32,
+) -> Vec<Color> {
+    let mut field: Option<GuidingField> = None;
+
+    for pass in 0..training_passes {
+        let samples = 1 << pass;
+        let recorder = Recorder::new(Grid {
+            bbox: world.bounding_box(),
+        });
+        eprintln!(
+            "Training pass {} of {training_passes}: {samples} spp",
+            pass + 1
+        );
+
+        let training = Pass {
+            cam,
+            field: field.as_ref(),
+            recorder: Some(&recorder),
+        };
+        cam.render_pixels(|i, j| {
+            for _ in 0..samples {
+                let r = cam.get_ray_at(
+                    i as f64 + sampler::random_double(),
+                    j as f64 + sampler::random_double(),
+                );
+                guided_color(&training, r, cam.max_depth(), world, lights);
+            }
+
+            Color::new(0.0, 0.0, 0.0)
+        });
+
+        field = Some(recorder.into_field());
+    }
+
+    let final_pass = Pass {
+        cam,
+        field: field.as_ref(),
+        recorder: None,
+    };
+    cam.render_pixels(|i, j| {
+        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+        for s_j in 0..cam.sqrt_spp() {
+            for s_i in 0..cam.sqrt_spp() {
+                let r = cam.get_ray(i, j, s_i, s_j);
+                pixel_color += guided_color(&final_pass, r, cam.max_depth(), world, lights);
+            }
+        }
+
+        cam.pixel_samples_scale() * pixel_color
+    })
+}
+
+// What a path traced in one pass needs besides the scene: the camera, the
+// field learned so far, if any, and during training the recorder learning
+// the next one.
+struct Pass<'a> {
+    cam: &'a Camera,
+    field: Option<&'a GuidingField>,
+    recorder: Option<&'a Recorder>,
+}
+
+// `ray_color` with the BSDF pdf replaced by its mix with the guide wherever
+// the guide has been trained, and with the incident radiance fed back into
+// the pass's recorder during training.
+fn guided_color(
+    pass: &Pass,
+    r: Ray,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+) -> Color {
+    if depth <= 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+        let camera_ray = depth == pass.cam.max_depth();
+        return pass.cam.background(r.direction(), camera_ray)
+            + light::environment(lights, r.direction());
+    };
+
+    // The medium crossed on the way absorbs part of whatever the ray finds.
+    r.transmittance(rec.t) * guided_hit_color(pass, r, &rec, depth, world, lights)
+}
+
+// Light leaving `rec` back along `r`, sampled with the guiding field.
+fn guided_hit_color(
+    pass: &Pass,
+    r: Ray,
+    rec: &HitRecord,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+) -> Color {
+    let color_from_emission = rec.mat.emitted(r, rec, rec.u, rec.v, rec.p);
+
+    match rec.mat.scatter(r, rec) {
+        None => color_from_emission,
+        Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+            attenuation * guided_color(pass, *ray, depth - 1, world, lights)
+        }
+        Some(ScatterRecord::Pdf { pdf_ptr, .. }) => {
+            let guided: Box<dyn Pdf + '_> = match pass.field.and_then(|f| f.pdf(rec.p)) {
+                Some(guide) => Box::new(GuidedBsdfPdf {
+                    guide,
+                    bsdf: pdf_ptr,
+                }),
+                None => pdf_ptr,
+            };
+
+            let light_pdf = HittablePdf::new(lights, rec.p);
+            let p = camera::light_mixture(&light_pdf, lights, guided, r);
+            let color_from_sources = light::direct_lighting(world, lights, r, rec);
+
+            // A direction nothing can scatter into ends the path.
+            let scattered = match p.generate() {
+                Some(direction) => rec.mat.scattered_ray(r, rec, direction),
+                None => return color_from_emission + color_from_sources,
+            };
+            let pdf_value = p.value(scattered.direction());
+            if pdf_value <= 0.0 {
+                return color_from_emission + color_from_sources;
+            }
+
+            let f = rec.mat.eval(r, rec, scattered);
+
+            let sample_color = guided_color(pass, scattered, depth - 1, world, lights);
+            if let Some(recorder) = pass.recorder {
+                recorder.record(
+                    rec.p,
+                    scattered.direction(),
+                    color::luminance(sample_color) / pdf_value,
+                );
+            }
+
+            let color_from_scatter = (f * sample_color) / pdf_value;
+            let color_from_scatter = color_from_scatter.clamp(0.0, 30.0);
+
+            color_from_emission + color_from_sources + color_from_scatter
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use crate::aabb::AABB;
+use crate::color::{self, Color};
 use crate::interval::Interval;
+use crate::light::{DirectionCone, Light};
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::vec3::{Point3, Vec3};
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
 use core::f64;
+use std::sync::Arc;
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
@@ -14,8 +19,42 @@ pub trait Hittable: Send + Sync {
     fn random(&self, _origin: Point3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+    // Area-measure counterpart of `random`: a uniformly chosen point on the
+    // surface (normal facing outward) and its density per unit area.
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        None
+    }
+    // Area density with which `random_on_surface` picks the point that the
+    // ray from `origin` along `direction` hits, or 0 if it misses.
+    fn area_pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+    // Emitted power (luminance times area and projected solid angle) in scene
+    // units, for choosing between lights in proportion to it.
+    fn power(&self) -> f64 {
+        0.0
+    }
+    // Lights without geometry that sampling should also consider, for light
+    // lists (see `HittableList::add_light`).
+    fn light_sources(&self) -> &[Box<dyn Light>] {
+        &[]
+    }
+    // Whether this is a primitive whose material emits light.
+    fn emits(&self) -> bool {
+        false
+    }
+    // The emitting primitives inside a composite, wrapped in the transforms
+    // above them, for building light lists (see `HittableList::lights_from`).
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        vec![]
+    }
+    // Bounds on the normals light leaves an emitter along, for `LightBVH`.
+    fn emission_cone(&self) -> DirectionCone {
+        DirectionCone::all()
+    }
 }
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
@@ -24,6 +63,10 @@ pub struct HitRecord<'a> {
     pub u: f64, //texture coords
     pub v: f64, //texture coords
     pub front_face: bool,
+    // Partial derivatives of `p` in `u` and `v`, for shading frames; zero
+    // where the surface has no parameterization.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
 }
 
 impl<'a> HitRecord<'a> {
@@ -44,18 +87,118 @@ impl<'a> HitRecord<'a> {
             u,
             v,
             front_face,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
         }
     }
+
+    pub fn with_tangents(self, tangent: Vec3, bitangent: Vec3) -> Self {
+        Self {
+            tangent,
+            bitangent,
+            ..self
+        }
+    }
+
+    // The frame around the normal that anisotropic materials shade in.
+    pub fn shading_frame(&self) -> Onb {
+        Onb::from_tangent(self.normal, self.tangent)
+    }
+
+    // Whether the hit counts given the material's opacity. Partial opacity is
+    // resolved by a hash of the point, so every look at it agrees.
+    pub fn is_opaque(&self) -> bool {
+        let opacity = self.mat.opacity(self);
+
+        opacity >= 1.0 || (opacity > 0.0 && sampler::hash_point(self.p) < opacity)
+    }
+
+    // Radiance emitted from this point towards `direction`, for records whose
+    // `normal` is the outward surface normal (such as `random_on_surface`'s).
+    pub fn emitted_towards(&self, direction: Vec3, time: f64) -> Color {
+        let front_face = vec3::dot(direction, self.normal) > 0.0;
+        let normal = if front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+        let facing = HitRecord::new(self.p, normal, self.mat, self.t, self.u, self.v, front_face);
+        let r_in = Ray::new(self.p + direction, -direction, time);
+
+        self.mat.emitted(r_in, &facing, self.u, self.v, self.p)
+    }
+}
+
+// Fraction of a surface parameterized over the unit square that
+// `HitRecord::is_opaque` keeps, averaged over a grid, which light sampling
+// divides its densities by.
+pub fn opaque_fraction<'a>(record_at: impl Fn(f64, f64) -> HitRecord<'a>) -> f64 {
+    grid_average(record_at, |rec| rec.mat.opacity(rec).clamp(0.0, 1.0))
+}
+
+// `Hittable::power` of a Lambertian emitter of the given area parameterized
+// over the unit square, from the luminance leaving both sides of its opaque
+// parts.
+pub fn emitted_power<'a>(area: f64, record_at: impl Fn(f64, f64) -> HitRecord<'a>) -> f64 {
+    let radiance = grid_average(record_at, |rec| {
+        let front = color::luminance(rec.emitted_towards(rec.normal, 0.0));
+        let back = color::luminance(rec.emitted_towards(-rec.normal, 0.0));
+
+        rec.mat.opacity(rec).clamp(0.0, 1.0) * (front + back)
+    });
+
+    f64::consts::PI * area * radiance
+}
+
+fn grid_average<'a>(
+    record_at: impl Fn(f64, f64) -> HitRecord<'a>,
+    f: impl Fn(&HitRecord<'a>) -> f64,
+) -> f64 {
+    let n = 32;
+    let mut sum = 0.0;
+
+    for i in 0..n {
+        for j in 0..n {
+            sum += f(&record_at(
+                (i as f64 + 0.5) / n as f64,
+                (j as f64 + 0.5) / n as f64,
+            ));
+        }
+    }
+
+    sum / (n * n) as f64
+}
+
+// A uniformly chosen point among the opaque parts of a surface parameterized
+// over the unit square, by rejection. Mostly transparent surfaces may come
+// back empty.
+pub fn random_opaque<'a>(record_at: impl Fn(f64, f64) -> HitRecord<'a>) -> Option<HitRecord<'a>> {
+    (0..64)
+        .map(|_| record_at(sampler::random_double(), sampler::random_double()))
+        .find(|rec| rec.is_opaque())
+}
+
+// `object` itself if it emits, or else the emitters inside it.
+pub fn emitters_of(object: &Arc<dyn Hittable>) -> Vec<Arc<dyn Hittable>> {
+    if object.emits() {
+        vec![object.clone()]
+    } else {
+        object.emitters()
+    }
 }
 
 pub struct Translate {
-    object: Box<dyn Hittable>,
+    object: Arc<dyn Hittable>,
     offset: Vec3,
     bbox: AABB,
 }
 
 impl Translate {
     pub fn new(object: Box<dyn Hittable>, offset: Vec3) -> Self {
+        Self::shared(Arc::from(object), offset)
+    }
+
+    fn shared(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
         let bbox = object.bounding_box() + offset;
 
         Self {
@@ -79,10 +222,44 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.object.random(origin - self.offset)
+    }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        self.object.random_on_surface().map(|(mut rec, pdf)| {
+            rec.p += self.offset;
+            (rec, pdf)
+        })
+    }
+
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.area_pdf_value(origin - self.offset, direction)
+    }
+
+    fn power(&self) -> f64 {
+        self.object.power()
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        emitters_of(&self.object)
+            .into_iter()
+            .map(|emitter| Arc::new(Self::shared(emitter, self.offset)) as Arc<dyn Hittable>)
+            .collect()
+    }
+
+    fn emission_cone(&self) -> DirectionCone {
+        self.object.emission_cone()
+    }
 }
 
 pub struct RotateY {
-    object: Box<dyn Hittable>,
+    object: Arc<dyn Hittable>,
     sin_theta: f64,
     cos_theta: f64,
     bbox: AABB,
@@ -91,8 +268,11 @@ pub struct RotateY {
 impl RotateY {
     pub fn new(object: Box<dyn Hittable>, angle: f64) -> Self {
         let radians = degrees_to_radians(angle);
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+
+        Self::shared(Arc::from(object), radians.sin(), radians.cos())
+    }
+
+    fn shared(object: Arc<dyn Hittable>, sin_theta: f64, cos_theta: f64) -> Self {
         let bbox = object.bounding_box();
 
         let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
@@ -129,34 +309,36 @@ impl RotateY {
     }
 }
 
+impl RotateY {
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            (self.cos_theta * v.x()) - (self.sin_theta * v.z()),
+            v.y(),
+            (self.sin_theta * v.x()) + (self.cos_theta * v.z()),
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            (self.cos_theta * v.x()) + (self.sin_theta * v.z()),
+            v.y(),
+            (-self.sin_theta * v.x()) + (self.cos_theta * v.z()),
+        )
+    }
+}
+
 impl Hittable for RotateY {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
-        let origin = Point3::new(
-            (self.cos_theta * r.origin().x()) - (self.sin_theta * r.origin().z()),
-            r.origin().y(),
-            (self.sin_theta * r.origin().x()) + (self.cos_theta * r.origin().z()),
-        );
-
-        let direction = Vec3::new(
-            (self.cos_theta * r.direction().x()) - (self.sin_theta * r.direction().z()),
-            r.direction().y(),
-            (self.sin_theta * r.direction().x()) + (self.cos_theta * r.direction().z()),
-        );
+        let origin = self.to_object(r.origin());
+        let direction = self.to_object(r.direction());
 
         let rotated_r = Ray::new(origin, direction, r.time());
 
         self.object.hit(&rotated_r, ray_t).map(|mut rec| {
-            rec.p = Point3::new(
-                (self.cos_theta * rec.p.x()) + (self.sin_theta * rec.p.z()),
-                rec.p.y(),
-                (-self.sin_theta * rec.p.x()) + (self.cos_theta * rec.p.z()),
-            );
-
-            rec.normal = Vec3::new(
-                (self.cos_theta * rec.normal.x()) + (self.sin_theta * rec.normal.z()),
-                rec.normal.y(),
-                (-self.sin_theta * rec.normal.x()) + (self.cos_theta * rec.normal.z()),
-            );
+            rec.p = self.to_world(rec.p);
+            rec.normal = self.to_world(rec.normal);
+            rec.tangent = self.to_world(rec.tangent);
+            rec.bitangent = self.to_world(rec.bitangent);
 
             rec
         })
@@ -165,6 +347,49 @@ impl Hittable for RotateY {
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object
+            .pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.to_world(self.object.random(self.to_object(origin)))
+    }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        self.object.random_on_surface().map(|(mut rec, pdf)| {
+            rec.p = self.to_world(rec.p);
+            rec.normal = self.to_world(rec.normal);
+            rec.tangent = self.to_world(rec.tangent);
+            rec.bitangent = self.to_world(rec.bitangent);
+            (rec, pdf)
+        })
+    }
+
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object
+            .area_pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+
+    fn power(&self) -> f64 {
+        self.object.power()
+    }
+
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        emitters_of(&self.object)
+            .into_iter()
+            .map(|emitter| {
+                Arc::new(Self::shared(emitter, self.sin_theta, self.cos_theta)) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    fn emission_cone(&self) -> DirectionCone {
+        let cone = self.object.emission_cone();
+
+        DirectionCone::new(self.to_world(cone.axis()), cone.cos_theta())
+    }
 }
 
 fn degrees_to_radians(degrees: f64) -> f64 {
@@ -1,13 +1,19 @@
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::alias_table::AliasTable;
+use crate::hittable::{self, HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::light::{self, Light};
 use crate::ray::Ray;
+use crate::sampler;
 use crate::vec3::{Point3, Vec3};
-use rand::Rng;
+use std::sync::{Arc, OnceLock};
 
 pub struct HittableList {
-    pub objects: Vec<Box<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
     bbox: AABB,
+    // Picks objects as lights by `light_weights`, built on first use.
+    selection: OnceLock<AliasTable>,
+    sources: Vec<Box<dyn Light>>,
 }
 
 impl HittableList {
@@ -15,17 +21,84 @@ impl HittableList {
         HittableList {
             objects: vec![],
             bbox: AABB::empty(),
+            selection: OnceLock::new(),
+            sources: vec![],
         }
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.add_shared(Arc::from(object));
+    }
+
+    fn add_shared(&mut self, object: Arc<dyn Hittable>) {
         let object_box = object.bounding_box();
         self.objects.push(object);
         self.bbox = AABB::from_aabb(self.bbox, object_box);
+        self.selection = OnceLock::new();
+    }
+
+    // A light list of every primitive in `world` whose material emits,
+    // sharing them with it.
+    pub fn lights_from(world: &dyn Hittable) -> Self {
+        let mut lights = HittableList::new();
+        for emitter in world.emitters() {
+            lights.add_shared(emitter);
+        }
+
+        lights
+    }
+
+    // Adds a light without geometry, which only light lists make use of.
+    pub fn add_light(&mut self, light: Box<dyn Light>) {
+        self.sources.push(light);
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.selection = OnceLock::new();
+        self.sources.clear();
+    }
+
+    // How likely each object is to be sampled as a light: its power. Objects
+    // that don't emit, such as glass added to steer samples towards
+    // caustics, weigh as much as an average light.
+    pub fn light_weights(&self) -> Vec<f64> {
+        let powers: Vec<f64> = self.objects.iter().map(|object| object.power()).collect();
+        let emitters = powers.iter().filter(|power| **power > 0.0).count();
+        let average = if emitters > 0 {
+            powers.iter().sum::<f64>() / emitters as f64
+        } else {
+            1.0
+        };
+
+        powers
+            .iter()
+            .map(|&power| if power > 0.0 { power } else { average })
+            .collect()
+    }
+
+    fn selection(&self) -> &AliasTable {
+        self.selection
+            .get_or_init(|| AliasTable::new(&self.light_weights()))
+    }
+
+    fn choose(&self) -> Option<(&dyn Hittable, f64)> {
+        let (index, pmf) = self.selection().sample()?;
+
+        Some((self.objects[index].as_ref(), pmf))
+    }
+
+    // Sum over the objects of `f` weighted by their selection probabilities.
+    fn weighted_sum(&self, f: impl Fn(&dyn Hittable) -> f64) -> f64 {
+        let selection = self.selection();
+
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| selection.pmf(*index) > 0.0)
+            .fold(0.0, |sum, (index, object)| {
+                sum + selection.pmf(index) * f(object.as_ref())
+            })
     }
 }
 
@@ -49,17 +122,65 @@ impl Hittable for HittableList {
     }
 
     fn pdf_value(&self, origin: crate::vec3::Point3, direction: crate::vec3::Vec3) -> f64 {
-        let weight = 1.0 / self.objects.len() as f64;
+        let share = light::geometry_share(&self.sources, !self.objects.is_empty());
+        let environment = light::environment_pdf_value(&self.sources, direction);
 
-        self.objects.iter().fold(0.0, |sum, object| {
-            sum + weight * object.pdf_value(origin, direction)
-        })
+        share * self.weighted_sum(|object| object.pdf_value(origin, direction))
+            + (1.0 - share) * environment
     }
 
     fn random(&self, origin: Point3) -> Vec3 {
-        let obj_len = self.objects.len();
-        let rand_index = rand::rng().random_range(0..obj_len);
+        let share = light::geometry_share(&self.sources, !self.objects.is_empty());
+        if sampler::random_double() >= share {
+            return light::random_environment(&self.sources, origin)
+                .unwrap_or(Vec3::new(1.0, 0.0, 0.0));
+        }
+
+        match self.choose() {
+            Some((object, _)) => object.random(origin),
+            None => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        let (object, prob) = self.choose()?;
+
+        object
+            .random_on_surface()
+            .map(|(rec, pdf)| (rec, pdf * prob))
+    }
+
+    // Only the first surface along `direction` could have been sampled at
+    // the point a ray from `origin` lands on.
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction, 0.0);
+        let mut closest_so_far = f64::INFINITY;
+        let mut closest_index = None;
+
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(rec) = object.hit(&r, Interval::new(0.001, closest_so_far)) {
+                closest_so_far = rec.t;
+                closest_index = Some(index);
+            }
+        }
+
+        closest_index.map_or(0.0, |index| {
+            self.selection().pmf(index) * self.objects[index].area_pdf_value(origin, direction)
+        })
+    }
+
+    fn power(&self) -> f64 {
+        self.objects.iter().map(|object| object.power()).sum()
+    }
+
+    fn light_sources(&self) -> &[Box<dyn Light>] {
+        &self.sources
+    }
 
-        self.objects[rand_index].random(origin)
+    fn emitters(&self) -> Vec<Arc<dyn Hittable>> {
+        self.objects
+            .iter()
+            .flat_map(hittable::emitters_of)
+            .collect()
     }
 }
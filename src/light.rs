@@ -0,0 +1,432 @@
+use core::f64;
+
+use crate::aabb::AABB;
+use crate::color::{self, Color};
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::EmissionSides;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
+
+// Light sources without geometry. Rays never hit them, so integrators reach
+// them only by sampling them from the point being lit (`sample_li`) and trace
+// light leaving them only from rays they sample themselves (`sample_le`).
+// They are added to a light list with `HittableList::add_light`.
+//
+// Environment lights are the exception: they surround the scene, so rays that
+// leave it see them (`le`). Light lists sample them alongside their geometry,
+// which lets integrators weigh them against BSDF sampling like any emitter.
+
+pub struct LightSample {
+    pub direction: Vec3, // unit vector towards the light
+    pub distance: f64,   // infinite for distant lights
+    pub radiance: Color, // incident radiance over the density of `direction`
+}
+
+pub trait Light: Send + Sync {
+    fn sample_li(&self, p: Point3) -> Option<LightSample>;
+    // A ray leaving the light and the flux it carries: radiance times cosine
+    // over the densities of its origin and direction. `bounds` encloses the
+    // scene, which distant lights shine on from outside.
+    fn sample_le(&self, bounds: &AABB, time: f64) -> Option<(Ray, Color)>;
+    // Luminous power in scene units, for choosing between lights.
+    fn power(&self, bounds: &AABB) -> f64;
+
+    fn is_environment(&self) -> bool {
+        false
+    }
+
+    // Radiance arriving along a ray that leaves the scene in `direction`.
+    fn le(&self, _direction: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Solid angle density of `sample_li` returning `direction`, for
+    // environment lights.
+    fn pdf_li(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+// `sample_li` with the shadow ray traced through `world`.
+pub fn sample_visible(
+    world: &dyn Hittable,
+    light: &dyn Light,
+    p: Point3,
+    time: f64,
+) -> Option<LightSample> {
+    let sample = light.sample_li(p)?;
+    let shadow = Ray::new(p, sample.direction, time);
+
+    world
+        .hit(&shadow, Interval::new(0.001, sample.distance - 0.001))
+        .is_none()
+        .then_some(sample)
+}
+
+// Light reaching `rec` straight from each of the sources in the light list
+// `lights`, reflected along `r_in`. Integrators add it at every vertex with a
+// BSDF, since no other sampling strategy can find these lights. Environment
+// lights are left out.
+pub fn direct_lighting(
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    r_in: Ray,
+    rec: &HitRecord,
+) -> Color {
+    lights
+        .light_sources()
+        .iter()
+        .filter(|light| !light.is_environment())
+        .filter_map(|light| sample_visible(world, light.as_ref(), rec.p, r_in.time()))
+        .fold(Color::new(0.0, 0.0, 0.0), |sum, sample| {
+            let towards_light = Ray::new(rec.p, sample.direction, r_in.time());
+            sum + rec.mat.eval(r_in, rec, towards_light) * sample.radiance
+        })
+}
+
+// Radiance reaching a ray that escapes the scene in `direction` from the
+// environment lights in the light list `lights`.
+pub fn environment(lights: &dyn Hittable, direction: Vec3) -> Color {
+    environment_lights(lights.light_sources()).fold(Color::new(0.0, 0.0, 0.0), |sum, light| {
+        sum + light.le(direction)
+    })
+}
+
+pub fn environment_lights(sources: &[Box<dyn Light>]) -> impl Iterator<Item = &dyn Light> {
+    sources
+        .iter()
+        .map(|light| light.as_ref())
+        .filter(|light| light.is_environment())
+}
+
+// The probability that a light list samples its geometry rather than its
+// environment lights: they split the samples evenly when it has both.
+pub fn geometry_share(sources: &[Box<dyn Light>], has_geometry: bool) -> f64 {
+    match (has_geometry, environment_lights(sources).next()) {
+        (_, None) => 1.0,
+        (true, Some(_)) => 0.5,
+        (false, Some(_)) => 0.0,
+    }
+}
+
+// A direction from one of the environment lights, each picked equally often.
+pub fn random_environment(sources: &[Box<dyn Light>], origin: Point3) -> Option<Vec3> {
+    let count = environment_lights(sources).count();
+    let index = ((sampler::random_double() * count as f64) as usize).min(count.checked_sub(1)?);
+
+    environment_lights(sources)
+        .nth(index)?
+        .sample_li(origin)
+        .map(|sample| sample.direction)
+}
+
+pub fn environment_pdf_value(sources: &[Box<dyn Light>], direction: Vec3) -> f64 {
+    let (count, sum) = environment_lights(sources).fold((0, 0.0), |(count, sum), light| {
+        (count + 1, sum + light.pdf_li(direction))
+    });
+
+    if count > 0 { sum / count as f64 } else { 0.0 }
+}
+
+// Radiant intensity `intensity` (W/sr) in every direction from `position`.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+
+    // A point light emitting `watts` in total, tinted by `color`.
+    pub fn from_power(position: Point3, color: Color, watts: f64) -> Self {
+        Self::new(position, watts / (4.0 * f64::consts::PI) * color)
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: to_light / distance_squared.sqrt(),
+            distance: distance_squared.sqrt(),
+            radiance: self.intensity / distance_squared,
+        })
+    }
+
+    fn sample_le(&self, _bounds: &AABB, time: f64) -> Option<(Ray, Color)> {
+        let direction = vec3::random_unit_vector();
+
+        Some((
+            Ray::new(self.position, direction, time),
+            4.0 * f64::consts::PI * self.intensity,
+        ))
+    }
+
+    fn power(&self, _bounds: &AABB) -> f64 {
+        4.0 * f64::consts::PI * color::luminance(self.intensity)
+    }
+}
+
+// A point light shining into a cone around `direction`, at full intensity
+// within `inner_angle` of its axis and falling off smoothly to nothing at
+// `outer_angle` (both in degrees, from the axis).
+pub struct SpotLight {
+    position: Point3,
+    frame: Onb,
+    intensity: Color,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let cos_outer = outer_angle.to_radians().cos();
+        let cos_inner = inner_angle.to_radians().cos().max(cos_outer);
+
+        Self {
+            position,
+            frame: Onb::new(direction),
+            intensity,
+            cos_inner,
+            cos_outer,
+        }
+    }
+
+    fn falloff(&self, direction: Vec3) -> f64 {
+        let cosine = vec3::dot(self.frame.w(), vec3::unit_vector(direction));
+        if cosine >= self.cos_inner {
+            return 1.0;
+        }
+
+        let t = ((cosine - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        let falloff = self.falloff(-to_light);
+        if distance_squared == 0.0 || falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: to_light / distance_squared.sqrt(),
+            distance: distance_squared.sqrt(),
+            radiance: falloff * self.intensity / distance_squared,
+        })
+    }
+
+    fn sample_le(&self, _bounds: &AABB, time: f64) -> Option<(Ray, Color)> {
+        let direction = self.frame.transform(random_in_cone(self.cos_outer));
+        let solid_angle = 2.0 * f64::consts::PI * (1.0 - self.cos_outer);
+
+        Some((
+            Ray::new(self.position, direction, time),
+            self.falloff(direction) * solid_angle * self.intensity,
+        ))
+    }
+
+    fn power(&self, _bounds: &AABB) -> f64 {
+        // Exact for the smoothstep falloff to within a few percent.
+        let solid_angle = 2.0 * f64::consts::PI * (1.0 - 0.5 * (self.cos_inner + self.cos_outer));
+
+        solid_angle * color::luminance(self.intensity)
+    }
+}
+
+// Light arriving from infinitely far away, such as the sun, from within
+// `angular_diameter` degrees around `to_light`. `irradiance` is what it
+// delivers to a surface facing it (W/m^2), whatever its angular size.
+pub struct DirectionalLight {
+    frame: Onb,
+    irradiance: Color,
+    cos_max: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(to_light: Vec3, irradiance: Color) -> Self {
+        Self {
+            frame: Onb::new(to_light),
+            irradiance,
+            cos_max: 1.0,
+        }
+    }
+
+    // The sun's disk is about 0.53 degrees across.
+    pub fn with_angular_diameter(self, degrees: f64) -> Self {
+        Self {
+            cos_max: (0.5 * degrees).to_radians().cos(),
+            ..self
+        }
+    }
+
+    // Radiance from within the cone over the density of sampling it
+    // uniformly, which tends to `irradiance` as the cone closes.
+    fn radiance_over_pdf(&self) -> Color {
+        if self.cos_max >= 1.0 {
+            return self.irradiance;
+        }
+
+        let sin_squared = 1.0 - self.cos_max * self.cos_max;
+
+        2.0 * (1.0 - self.cos_max) / sin_squared * self.irradiance
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3) -> Option<LightSample> {
+        let direction = if self.cos_max >= 1.0 {
+            self.frame.w()
+        } else {
+            self.frame.transform(random_in_cone(self.cos_max))
+        };
+
+        Some(LightSample {
+            direction: vec3::unit_vector(direction),
+            distance: f64::INFINITY,
+            radiance: self.radiance_over_pdf(),
+        })
+    }
+
+    // Rays start on a disk facing the light just outside the scene's
+    // bounding sphere, so the flux is what crosses that disk.
+    fn sample_le(&self, bounds: &AABB, time: f64) -> Option<(Ray, Color)> {
+        let (center, radius) = bounding_sphere(bounds);
+        let disk = vec3::random_in_unit_disk();
+        let origin = center
+            + radius * (self.frame.w() + disk.x() * self.frame.u() + disk.y() * self.frame.v());
+        let direction = if self.cos_max >= 1.0 {
+            -self.frame.w()
+        } else {
+            -self.frame.transform(random_in_cone(self.cos_max))
+        };
+        let cosine = -vec3::dot(vec3::unit_vector(direction), self.frame.w());
+        let disk_area = f64::consts::PI * radius * radius;
+
+        Some((
+            Ray::new(origin, direction, time),
+            cosine * disk_area * self.radiance_over_pdf(),
+        ))
+    }
+
+    fn power(&self, bounds: &AABB) -> f64 {
+        let (_, radius) = bounding_sphere(bounds);
+
+        f64::consts::PI * radius * radius * color::luminance(self.irradiance)
+    }
+}
+
+// A direction within `acos(cos_max)` of the z axis, uniform in solid angle.
+fn random_in_cone(cos_max: f64) -> Vec3 {
+    let z = 1.0 - sampler::random_double() * (1.0 - cos_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * f64::consts::PI * sampler::random_double();
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn bounding_sphere(bounds: &AABB) -> (Point3, f64) {
+    let (x, y, z) = (
+        bounds.axis_interval(0),
+        bounds.axis_interval(1),
+        bounds.axis_interval(2),
+    );
+    let min = Point3::new(x.min, y.min, z.min);
+    let max = Point3::new(x.max, y.max, z.max);
+
+    (0.5 * (min + max), 0.5 * (max - min).length())
+}
+
+// The directions within `acos(cos_theta)` of `w`.
+#[derive(Clone, Copy)]
+pub struct DirectionCone {
+    w: Vec3,
+    cos_theta: f64,
+}
+
+impl DirectionCone {
+    pub fn new(w: Vec3, cos_theta: f64) -> Self {
+        Self {
+            w: vec3::unit_vector(w),
+            cos_theta,
+        }
+    }
+
+    pub fn all() -> Self {
+        Self::new(Vec3::new(0.0, 0.0, 1.0), -1.0)
+    }
+
+    // Normals that light can leave a flat emitter along, given the sides it
+    // emits from.
+    pub fn emitted_from(normal: Vec3, sides: EmissionSides) -> Self {
+        match sides {
+            EmissionSides::Front => Self::new(normal, 1.0),
+            EmissionSides::Back => Self::new(-normal, 1.0),
+            EmissionSides::Both => Self::all(),
+        }
+    }
+
+    pub fn axis(&self) -> Vec3 {
+        self.w
+    }
+
+    pub fn cos_theta(&self) -> f64 {
+        self.cos_theta
+    }
+
+    // The smallest cone holding both.
+    pub fn union(a: DirectionCone, b: DirectionCone) -> DirectionCone {
+        let theta_a = a.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_b = b.cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_d = vec3::dot(a.w, b.w).clamp(-1.0, 1.0).acos();
+
+        if (theta_d + theta_b).min(f64::consts::PI) <= theta_a {
+            return a;
+        }
+        if (theta_d + theta_a).min(f64::consts::PI) <= theta_b {
+            return b;
+        }
+
+        let theta_o = 0.5 * (theta_a + theta_d + theta_b);
+        if theta_o >= f64::consts::PI {
+            return Self::all();
+        }
+
+        // Rotate a's axis towards b's until the cone just covers both.
+        let axis = vec3::cross(a.w, b.w);
+        if axis.length_squared() == 0.0 {
+            return Self::all();
+        }
+
+        let k = vec3::unit_vector(axis);
+        let theta_r = theta_o - theta_a;
+        let w = a.w * theta_r.cos()
+            + vec3::cross(k, a.w) * theta_r.sin()
+            + k * vec3::dot(k, a.w) * (1.0 - theta_r.cos());
+
+        Self::new(w, theta_o.cos())
+    }
+}
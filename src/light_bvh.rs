@@ -0,0 +1,365 @@
+use core::f64;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::light::{self, DirectionCone, Light};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
+
+// A light list for scenes with many emitters, after Conty Estevez and Kulla's
+// "Importance Sampling of Many Lights with Adaptive Tree Splitting" as used in
+// PBRT-v4. The emitters are grouped in a BVH whose nodes bound their position,
+// power and the directions they emit in; sampling walks down from the root,
+// picking each child in proportion to a conservative estimate of how much it
+// can light the shading point. Sampling with no shading point (light subpaths)
+// falls back on the list's power-proportional selection.
+
+// What a node knows about the emitters below it. Every emitter is a surface
+// emitting within 90 degrees of its normals.
+#[derive(Clone, Copy)]
+struct LightBounds {
+    bbox: AABB,
+    phi: f64,
+    normals: DirectionCone,
+}
+
+impl LightBounds {
+    fn union(a: LightBounds, b: LightBounds) -> LightBounds {
+        LightBounds {
+            bbox: AABB::from_aabb(a.bbox, b.bbox),
+            phi: a.phi + b.phi,
+            normals: DirectionCone::union(a.normals, b.normals),
+        }
+    }
+
+    // An upper bound on how much the emitters can light `p`, up to a common
+    // factor: power over squared distance, times the largest cosine between
+    // any of their normals and any direction from them towards `p`.
+    fn importance(&self, p: Point3) -> f64 {
+        if self.phi <= 0.0 {
+            return 0.0;
+        }
+
+        let (center, radius) = light::bounding_sphere(&self.bbox);
+        let to_p = p - center;
+        let distance_squared = to_p.length_squared();
+        let theta_w = vec3::dot(self.normals.axis(), vec3::unit_vector(to_p))
+            .clamp(-1.0, 1.0)
+            .acos();
+        let theta_o = self.normals.cos_theta().clamp(-1.0, 1.0).acos();
+        let theta_b = if distance_squared <= radius * radius {
+            f64::consts::PI
+        } else {
+            (radius / distance_squared.sqrt()).asin()
+        };
+
+        let theta = (theta_w - theta_o - theta_b).max(0.0);
+        if theta >= 0.5 * f64::consts::PI {
+            return 0.0;
+        }
+
+        // Clamped so that points inside the bounds don't blow up.
+        self.phi * theta.cos() / distance_squared.max(radius)
+    }
+}
+
+enum LightNode {
+    Leaf {
+        index: usize,
+        bounds: LightBounds,
+    },
+    Internal {
+        left: Box<LightNode>,
+        right: Box<LightNode>,
+        bounds: LightBounds,
+    },
+}
+
+impl LightNode {
+    fn node(mut leaves: Vec<(usize, LightBounds)>) -> Self {
+        if leaves.len() == 1 {
+            let (index, bounds) = leaves[0];
+            return Self::Leaf { index, bounds };
+        }
+
+        let bbox = leaves
+            .iter()
+            .fold(AABB::empty(), |bbox, (_, b)| AABB::from_aabb(bbox, b.bbox));
+        let axis = bbox.longest_axis();
+
+        leaves.sort_by(|(_, a), (_, b)| {
+            let a_min = a.bbox.axis_interval(axis).min;
+            let b_min = b.bbox.axis_interval(axis).min;
+            a_min.total_cmp(&b_min)
+        });
+        let right_half = leaves.split_off(leaves.len() / 2);
+        let left = Self::node(leaves);
+        let right = Self::node(right_half);
+        let bounds = LightBounds::union(left.bounds(), right.bounds());
+
+        Self::Internal {
+            left: Box::new(left),
+            right: Box::new(right),
+            bounds,
+        }
+    }
+
+    fn bounds(&self) -> LightBounds {
+        match self {
+            Self::Leaf { bounds, .. } => *bounds,
+            Self::Internal { bounds, .. } => *bounds,
+        }
+    }
+
+    fn hit<'a>(
+        &self,
+        objects: &'a [Arc<dyn Hittable>],
+        r: &Ray,
+        ray_t: Interval,
+    ) -> Option<HitRecord<'a>> {
+        if !self.bounds().bbox.hit(r, ray_t) {
+            return None;
+        }
+
+        match self {
+            Self::Leaf { index, .. } => objects[*index].hit(r, ray_t),
+            Self::Internal { left, right, .. } => {
+                let left_hit = left.hit(objects, r, ray_t);
+                let max = left_hit.as_ref().map_or(ray_t.max, |rec| rec.t);
+
+                right
+                    .hit(objects, r, Interval::new(ray_t.min, max))
+                    .or(left_hit)
+            }
+        }
+    }
+
+    // Solid angle density of `direction` from `origin`, given the probability
+    // `pmf` of having reached this node.
+    fn pdf_value(
+        &self,
+        objects: &[Arc<dyn Hittable>],
+        origin: Point3,
+        direction: Vec3,
+        pmf: f64,
+    ) -> f64 {
+        let r = Ray::new(origin, direction, 0.0);
+        if !self
+            .bounds()
+            .bbox
+            .hit(&r, Interval::new(0.001, f64::INFINITY))
+        {
+            return 0.0;
+        }
+
+        match self {
+            Self::Leaf { index, .. } => pmf * objects[*index].pdf_value(origin, direction),
+            Self::Internal { left, right, .. } => {
+                let (p_left, p_right) = child_probabilities(origin, left, right);
+                let mut pdf = 0.0;
+                if p_left > 0.0 {
+                    pdf += left.pdf_value(objects, origin, direction, pmf * p_left);
+                }
+                if p_right > 0.0 {
+                    pdf += right.pdf_value(objects, origin, direction, pmf * p_right);
+                }
+
+                pdf
+            }
+        }
+    }
+}
+
+// How likely each child is to be picked when lighting `p`. When neither can
+// light it, they are picked by power instead so that sampling still has
+// somewhere to go that `pdf_value` agrees with.
+fn child_probabilities(p: Point3, left: &LightNode, right: &LightNode) -> (f64, f64) {
+    let (left, right) = (left.bounds(), right.bounds());
+    let (i_left, i_right) = (left.importance(p), right.importance(p));
+
+    if i_left + i_right > 0.0 {
+        (i_left / (i_left + i_right), i_right / (i_left + i_right))
+    } else if left.phi + right.phi > 0.0 {
+        (
+            left.phi / (left.phi + right.phi),
+            right.phi / (left.phi + right.phi),
+        )
+    } else {
+        (0.5, 0.5)
+    }
+}
+
+pub struct LightBVH {
+    lights: HittableList,
+    root: Option<LightNode>,
+}
+
+impl LightBVH {
+    pub fn new(lights: HittableList) -> Self {
+        let weights = lights.light_weights();
+        let leaves: Vec<(usize, LightBounds)> = lights
+            .objects
+            .iter()
+            .zip(weights)
+            .enumerate()
+            .map(|(index, (object, phi))| {
+                let bounds = LightBounds {
+                    bbox: object.bounding_box(),
+                    phi,
+                    normals: object.emission_cone(),
+                };
+                (index, bounds)
+            })
+            .collect();
+        let root = (!leaves.is_empty()).then(|| LightNode::node(leaves));
+
+        Self { lights, root }
+    }
+}
+
+impl Hittable for LightBVH {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        self.root.as_ref()?.hit(&self.lights.objects, r, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.lights.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let sources = self.lights.light_sources();
+        let share = light::geometry_share(sources, self.root.is_some());
+        let environment = light::environment_pdf_value(sources, direction);
+
+        let geometry = match &self.root {
+            Some(root) => root.pdf_value(&self.lights.objects, origin, direction, 1.0),
+            None => 0.0,
+        };
+
+        share * geometry + (1.0 - share) * environment
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        let sources = self.lights.light_sources();
+        let share = light::geometry_share(sources, self.root.is_some());
+        if sampler::random_double() >= share {
+            return light::random_environment(sources, origin).unwrap_or(Vec3::new(1.0, 0.0, 0.0));
+        }
+
+        let Some(mut node) = self.root.as_ref() else {
+            return Vec3::new(1.0, 0.0, 0.0);
+        };
+
+        loop {
+            match node {
+                LightNode::Leaf { index, .. } => return self.lights.objects[*index].random(origin),
+                LightNode::Internal { left, right, .. } => {
+                    let (p_left, _) = child_probabilities(origin, left, right);
+                    node = if sampler::random_double() < p_left {
+                        left
+                    } else {
+                        right
+                    };
+                }
+            }
+        }
+    }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        self.lights.random_on_surface()
+    }
+
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.lights.area_pdf_value(origin, direction)
+    }
+
+    fn power(&self) -> f64 {
+        self.lights.power()
+    }
+
+    fn light_sources(&self) -> &[Box<dyn Light>] {
+        self.lights.light_sources()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::DiffuseLight;
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+
+    // Every direction towards a light has density `pdf_value`, which for
+    // lights that don't overlap from the shading point is the probability of
+    // picking the light times its own density there. How often samples land
+    // on each light should match that probability.
+    #[test]
+    fn sampling_matches_pdf_value() {
+        let light = |radiance: f64| {
+            Box::new(DiffuseLight::from_color(
+                Color::new(1.0, 1.0, 1.0) * radiance,
+            ))
+        };
+        let mut list = HittableList::new();
+        list.add(Box::new(Quad::new(
+            Point3::new(-2.0, 3.0, -2.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            light(4.0),
+        )));
+        list.add(Box::new(Quad::new(
+            Point3::new(1.5, 2.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.5),
+            light(1.0),
+        )));
+        list.add(Box::new(Sphere::new_static(
+            Point3::new(0.0, 1.0, 3.0),
+            0.5,
+            light(2.0),
+        )));
+        list.add(Box::new(Quad::new(
+            Point3::new(-4.0, -1.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            light(8.0),
+        )));
+        let bvh = LightBVH::new(HittableList::lights_from(&list));
+        let objects = &bvh.lights.objects;
+
+        let origin = Point3::new(0.2, 0.0, 0.3);
+        let n = 200_000;
+        let mut counts = vec![0; objects.len()];
+        let mut probabilities = vec![0.0; objects.len()];
+        for _ in 0..n {
+            let direction = bvh.random(origin);
+            let r = Ray::new(origin, direction, 0.0);
+            let i = (0..objects.len())
+                .find(|&i| {
+                    objects[i]
+                        .hit(&r, Interval::new(0.001, f64::INFINITY))
+                        .is_some()
+                })
+                .expect("a sample missing every light");
+
+            counts[i] += 1;
+            probabilities[i] =
+                bvh.pdf_value(origin, direction) / objects[i].pdf_value(origin, direction);
+        }
+
+        let total: f64 = probabilities.iter().sum();
+        assert!((total - 1.0).abs() < 1e-6, "probabilities sum to {total}");
+        for (i, (&count, &probability)) in counts.iter().zip(&probabilities).enumerate() {
+            let frequency = count as f64 / n as f64;
+            assert!(
+                (frequency - probability).abs() < 0.005,
+                "light {i}: frequency {frequency}, probability {probability}"
+            );
+        }
+    }
+}
@@ -1,49 +1,77 @@
+use crate::background::{Gradient, TextureBackground};
 use crate::bvh::BVHNode;
-use crate::camera::Camera;
+use crate::camera::{Camera, Integrator};
 use crate::color::Color;
 use crate::constant_medium::ConstantMedium;
+use crate::environment::EnvironmentLight;
 use crate::hittable::{RotateY, Translate};
 use crate::hittable_list::HittableList;
-use crate::material::{Dielectric, DiffuseLight};
-use crate::material::{Lambertian, Metal};
+use crate::light::{DirectionalLight, PointLight, SpotLight};
+use crate::light_bvh::LightBVH;
+use crate::material::{Conductor, Cutout, Dielectric, DiffuseLight, DispersiveDielectric};
+use crate::material::{EmissionSides, Lambertian, Layered, Material, Metal, NormalMapped};
+use crate::material::{OrenNayar, Principled, RoughDielectric};
 use crate::obj_loader::load_obj;
 use crate::quad::Quad;
 use crate::rtw_stb_image::ImageTexture;
+use crate::sky::Sky;
 use crate::sphere::Sphere;
-use crate::texture::{CheckerTexture, PerlinNoise};
+use crate::subsurface::Subsurface;
+use crate::texture::{CheckerTexture, PerlinNoise, SolidColor, Texture};
 use crate::vec3::{Point3, Vec3};
 use core::f64;
 use hittable::Hittable;
 use rand::Rng;
-use std::sync::Arc;
 use std::time::Instant;
 
 mod aabb;
+mod alias_table;
+mod background;
+mod bdpt;
 mod bvh;
 mod camera;
 mod color;
 mod constant_medium;
+mod distribution;
+mod environment;
+mod guiding;
 mod hittable;
 mod hittable_list;
 mod integrate_x_sq;
 mod interval;
+mod light;
+mod light_bvh;
 mod material;
+mod microfacet;
+mod mlt;
 mod obj_loader;
 mod onb;
 mod pdf;
 mod perlin;
+mod photon_map;
 mod pi;
 mod quad;
 mod ray;
 mod rtw_stb_image;
+mod sampler;
+mod sky;
+mod spectral;
+mod spectrum;
 mod sphere;
 mod sphere_importance;
+mod subsurface;
 mod texture;
 mod triangle;
 mod vec3;
 
 fn main() {
-    match 8 {
+    // The scene to render can be given on the command line, by number.
+    let scene = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(8);
+
+    match scene {
         // 1 => {
         //     bouncing_spheres();
         // }
@@ -66,7 +94,7 @@ fn main() {
         //     simple_light();
         // }
         8 => {
-            cornell_box();
+            cornell_box(Integrator::PathTracer);
         }
         9 => {
             cornell_smoke();
@@ -89,6 +117,178 @@ fn main() {
         // 15 => {
         //     sphere_importance::calc();
         // }
+        16 => {
+            cornell_box(Integrator::Bdpt);
+        }
+        17 => {
+            cornell_caustics(
+                Box::new(Dielectric::new(1.5)),
+                Integrator::PhotonMap {
+                    photons: 1_000_000,
+                    nearest: 100,
+                    radius: 20.0,
+                },
+            );
+        }
+        18 => {
+            cornell_caustics(
+                Box::new(Dielectric::new(1.5)),
+                Integrator::Sppm {
+                    iterations: 100,
+                    photons: 200_000,
+                    radius: 10.0,
+                },
+            );
+        }
+        19 => {
+            cornell_caustics(
+                Box::new(Dielectric::new(1.5)),
+                Integrator::Mlt {
+                    mutations_per_pixel: 100,
+                    bootstrap_samples: 100_000,
+                    chains: 1_000,
+                },
+            );
+        }
+        20 => {
+            cornell_box(Integrator::Guided { training_passes: 6 });
+        }
+        21 => {
+            cornell_caustics(
+                Box::new(DispersiveDielectric::diamond()),
+                Integrator::Spectral,
+            );
+        }
+        22 => {
+            cornell_subsurface(Integrator::PathTracer);
+        }
+        23 => {
+            cornell_spotlight(Integrator::PathTracer);
+        }
+        24 => {
+            cornell_many_lights(Integrator::PathTracer);
+        }
+        25 => {
+            // Any image the `image` crate reads, such as an HDR panorama, can
+            // be given after the scene number.
+            let filename = std::env::args()
+                .nth(2)
+                .unwrap_or_else(|| "earthmap.jpg".to_string());
+            environment_map(&filename, Integrator::PathTracer);
+        }
+        26 => {
+            daylight(Integrator::PathTracer);
+        }
+        27 => {
+            gradient_sky();
+        }
+        28 => {
+            studio();
+        }
+        29 => {
+            // Polished gold and silver, then rough copper and brushed
+            // aluminium, its grooves running around the sphere.
+            cornell_spheres(
+                vec![
+                    Box::new(Conductor::gold()),
+                    Box::new(Conductor::silver()),
+                    Box::new(Conductor::copper().with_roughness(0.25)),
+                    Box::new(Conductor::aluminium().with_anisotropic_roughness(0.05, 0.4)),
+                ],
+                Integrator::PathTracer,
+            );
+        }
+        30 => {
+            // Smooth glass, then frosted glass growing rougher, the last
+            // tinted by absorption on the way through.
+            cornell_spheres(
+                vec![
+                    Box::new(Dielectric::new(1.5)),
+                    Box::new(RoughDielectric::new(1.5, 0.1)),
+                    Box::new(RoughDielectric::new(1.5, 0.3)),
+                    Box::new(
+                        RoughDielectric::new(1.5, 0.3)
+                            .with_absorption(Color::new(0.015, 0.005, 0.002)),
+                    ),
+                ],
+                Integrator::PathTracer,
+            );
+        }
+        31 => {
+            // Clear-coated plastic, brushed copper, velvety cloth and
+            // frosted green glass, all from the one principled material.
+            let value = |v: f64| -> Box<dyn Texture> { Box::new(SolidColor::from_value(v)) };
+            cornell_spheres(
+                vec![
+                    Box::new(
+                        Principled::from_color(Color::new(0.7, 0.1, 0.1))
+                            .with_roughness(value(0.4))
+                            .with_clearcoat(value(1.0)),
+                    ),
+                    Box::new(
+                        Principled::from_color(Color::new(0.95, 0.64, 0.54))
+                            .with_metallic(value(1.0))
+                            .with_roughness(value(0.35))
+                            .with_anisotropy(value(0.8)),
+                    ),
+                    Box::new(
+                        Principled::from_color(Color::new(0.15, 0.2, 0.5))
+                            .with_roughness(value(0.9))
+                            .with_sheen(value(1.0)),
+                    ),
+                    Box::new(
+                        Principled::from_color(Color::new(0.8, 0.95, 0.85))
+                            .with_roughness(value(0.15))
+                            .with_transmission(value(1.0)),
+                    ),
+                ],
+                Integrator::PathTracer,
+            );
+        }
+        32 => {
+            // The same clay as a Lambertian surface and ever rougher
+            // Oren-Nayar ones, which flatten out towards the edges.
+            let clay = Color::new(0.7, 0.45, 0.3);
+            cornell_spheres(
+                vec![
+                    Box::new(Lambertian::from_color(clay)),
+                    Box::new(OrenNayar::from_color(clay, 0.5)),
+                    Box::new(OrenNayar::from_color(clay, 1.0)),
+                ],
+                Integrator::PathTracer,
+            );
+        }
+        33 => {
+            // Glossy car paint, a satin coat over rough copper, and white
+            // plaster under clear and amber varnish.
+            let plaster = || Box::new(OrenNayar::from_color(Color::new(0.8, 0.8, 0.8), 0.5));
+            cornell_spheres(
+                vec![
+                    Box::new(Layered::new(
+                        Box::new(Lambertian::from_color(Color::new(0.05, 0.15, 0.5))),
+                        1.5,
+                        0.0,
+                    )),
+                    Box::new(Layered::new(
+                        Box::new(Conductor::copper().with_roughness(0.4)),
+                        1.5,
+                        0.2,
+                    )),
+                    Box::new(Layered::new(plaster(), 1.5, 0.05)),
+                    Box::new(
+                        Layered::new(plaster(), 1.5, 0.05)
+                            .with_absorption(Color::new(0.1, 0.4, 1.2)),
+                    ),
+                ],
+                Integrator::PathTracer,
+            );
+        }
+        34 => {
+            cornell_relief(Integrator::PathTracer);
+        }
+        35 => {
+            cornell_leaves(Integrator::PathTracer);
+        }
         _ => {
             ();
         }
@@ -189,12 +389,12 @@ fn buddha_box() {
     // ));
     // let light_sphere = Box::new(Translate::new(light_sphere, Vec3::new(-1.0, 1.5, -3.0)));
 
-    // let buddha_mat = Arc::new(Metal::new(Color::new(0.75, 0.6, 0.35), 0.5));
-    let buddha_mat = Arc::new(Dielectric::new(1.5));
+    // let buddha_mat = Box::new(Metal::new(Color::new(0.75, 0.6, 0.35), 0.5));
+    let buddha_mat = Box::new(Dielectric::new(1.5));
     let buddha = Box::new(load_obj("buddha/buddha.obj", 8.0, buddha_mat));
     let buddha = Box::new(Translate::new(buddha, Vec3::new(-5.0, 3.0, -1.0)));
 
-    let fog_buddha_mat = Arc::new(Lambertian::from_color(Color::new(1.0, 1.0, 1.0)));
+    let fog_buddha_mat = Box::new(Lambertian::from_color(Color::new(1.0, 1.0, 1.0)));
     let fog_buddha = Box::new(load_obj("buddha/buddha.obj", 8.0, fog_buddha_mat));
     let fog_buddha = Box::new(Translate::new(fog_buddha, Vec3::new(-5.0, 3.0, -1.0)));
     let fog_buddha = Box::new(ConstantMedium::from_color_emit(
@@ -217,23 +417,7 @@ fn buddha_box() {
     world.add(fog);
     let bvh = BVHNode::from_list(world);
 
-    let mut lights = HittableList::new();
-    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
-    let hittable_light = Box::new(Quad::new(
-        Point3::new(-4.0, 9.99, 1.0),
-        Vec3::new(2.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -2.0),
-        light,
-    ));
-
-    // let hittable_light_mat = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
-    // let hittable_light_sphere = Box::new(Sphere::new_static(
-    //     Point3::new(-1.0, 1.5, -3.0),
-    //     1.0,
-    //     hittable_light_mat,
-    // ));
-    lights.add(hittable_light);
-    // lights.add(hittable_light_sphere);
+    let lights = HittableList::lights_from(&bvh);
 
     let aspect_ratio: f64 = 1.0;
     let image_width: f64 = 600.0;
@@ -320,12 +504,13 @@ fn teapot_box() {
         white3,
     ));
 
-    // let teapot_mat = Arc::new(Lambertian::from_color(Color::new(0.8, 0.5, 0.2)));
-    let teapot_mat = Arc::new(Dielectric::new(1.5));
+    // let teapot_mat = Box::new(Lambertian::from_color(Color::new(0.8, 0.5, 0.2)));
+    let teapot_mat = Box::new(Dielectric::new(1.5));
+    // let teapot_mat = Box::new(RoughDielectric::new(1.5, 0.3));
     let teapot = Box::new(load_obj("teapot.obj", 1.0, teapot_mat));
     let teapot = Box::new(Translate::new(teapot, Vec3::new(-3.0, 0.0, -2.5)));
 
-    let teapot_mat2 = Arc::new(Dielectric::new(1.5));
+    let teapot_mat2 = Box::new(Dielectric::new(1.5));
     let teapot2 = Box::new(load_obj("teapot.obj", 1.0, teapot_mat2));
     let teapot2 = Box::new(Translate::new(teapot2, Vec3::new(-3.0, 0.0, -2.5)));
     world.add(Box::new(ConstantMedium::from_color(
@@ -343,15 +528,7 @@ fn teapot_box() {
     world.add(teapot);
     let bvh = BVHNode::from_list(world);
 
-    let mut lights = HittableList::new();
-    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
-    let hittable_light = Box::new(Quad::new(
-        Point3::new(-1.0, 9.9, 1.0),
-        Vec3::new(-2.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -2.0),
-        light,
-    ));
-    lights.add(hittable_light);
+    let lights = HittableList::lights_from(&bvh);
 
     let aspect_ratio: f64 = 1.0;
     let image_width: f64 = 600.0;
@@ -500,14 +677,7 @@ fn final_scene(image_width: i32, samples_per_pixel: i32, max_depth: i32) {
         Vec3::new(-100.0, 270.0, 395.0),
     )));
 
-    let mut lights = HittableList::new();
-    let hittable_light = Box::new(Quad::new(
-        Point3::new(123.0, 554.0, 147.0),
-        Vec3::new(300.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 265.0),
-        Box::new(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0))),
-    ));
-    lights.add(hittable_light);
+    let lights = HittableList::lights_from(&world);
 
     let aspect_ratio: f64 = 1.0;
     let image_width = image_width;
@@ -638,13 +808,6 @@ fn cornell_smoke() {
         Color::new(0.2, 0.3, 0.8),
     ));
 
-    let lights = Quad::new(
-        Point3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0))),
-    );
-
     world.add(quad1);
     world.add(quad2);
     world.add(quad3);
@@ -655,6 +818,8 @@ fn cornell_smoke() {
     world.add(box2);
     let bvh = BVHNode::from_list(world);
 
+    let lights = HittableList::lights_from(&bvh);
+
     let aspect_ratio: f64 = 1.0;
     let image_width: f64 = 600.0;
     let samples_per_pixel = 10_000;
@@ -688,126 +853,517 @@ fn cornell_smoke() {
     cam.render(&bvh, &lights);
 }
 
-fn cornell_box() {
+fn cornell_box(integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+    world.add(Box::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+    )));
+
+    let box1 = quad::make_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 330.0, 165.0),
+        || Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
+        // || Box::new(Metal::new(Color::new(0.73, 0.73, 0.73), 0.0)),
+    );
+    let box1 = Box::new(RotateY::new(box1, 15.0));
+    let box1 = Box::new(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
+
+    let box2 = quad::make_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 165.0, 165.0),
+        || Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
+        // || Box::new(Metal::new(Color::new(0.73, 0.73, 0.73), 0.0)),
+    );
+    let box2 = Box::new(RotateY::new(box2, -18.0));
+    let box2 = Box::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
+
+    // let glass = Box::new(Dielectric::new(1.5));
+    // let sphere = Box::new(Sphere::new_static(
+    //     Point3::new(190.0, 90.0, 190.0),
+    //     90.0,
+    //     glass,
+    // ));
+
+    world.add(box1);
+    world.add(box2);
+    // world.add(sphere);
+
+    let lights = HittableList::lights_from(&world);
+
+    let cam = cornell_camera(1_000, 50);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn cornell_caustics(glass: Box<dyn Material>, integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+    world.add(Box::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+    )));
+
+    // A glass ball focusing the light into a caustic on the floor, and a
+    // mirror ball throwing it onto the walls.
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(190.0, 90.0, 190.0),
+        90.0,
+        glass,
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(370.0, 90.0, 370.0),
+        90.0,
+        Box::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0)),
+    )));
+
+    let lights = HittableList::lights_from(&world);
+
+    let cam = cornell_camera(100, 50);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+// A row of spheres across the Cornell box, one for each material from left to
+// right, for comparing materials side by side under the same light.
+fn cornell_spheres(materials: Vec<Box<dyn Material>>, integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+    world.add(Box::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+    )));
+
+    // Seen from the camera, +x is to the left.
+    let spacing = 555.0 / materials.len() as f64;
+    let radius = 0.4 * spacing;
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Box::new(Sphere::new_static(
+            Point3::new(555.0 - spacing * (i as f64 + 0.5), radius, 200.0),
+            radius,
+            material,
+        )));
+    }
+
+    let lights = HittableList::lights_from(&world);
+
+    let cam = cornell_camera(500, 50);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn cornell_relief(integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+    world.add(Box::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+    )));
+
+    // The world map embossed on a gold plaque on the back wall, its bright
+    // land standing out of the sea, and in relief on a globe below.
+    let relief = || Box::new(ImageTexture::new("earthmap.jpg"));
+    let plaque = NormalMapped::from_bump(
+        Box::new(Conductor::gold().with_roughness(0.3)),
+        relief(),
+        2.0,
+    );
+    world.add(Box::new(Quad::new(
+        Point3::new(458.0, 250.0, 500.0),
+        Vec3::new(-360.0, 0.0, 0.0),
+        Vec3::new(0.0, 180.0, 0.0),
+        Box::new(plaque),
+    )));
+
+    // A hammered silver tray for it to stand on, its dimples all in the
+    // normal map.
+    let tray = NormalMapped::from_normal_map(
+        Box::new(Conductor::silver().with_roughness(0.15)),
+        Box::new(ImageTexture::new("dimples_normal.png")),
+    );
+    world.add(Box::new(Quad::new(
+        Point3::new(428.0, 0.5, 100.0),
+        Vec3::new(-300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 300.0),
+        Box::new(tray),
+    )));
+
+    let globe = NormalMapped::from_bump(Box::new(Lambertian::new(relief())), relief(), 4.0);
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(278.0, 90.0, 250.0),
+        90.0,
+        Box::new(globe),
+    )));
+
+    let lights = HittableList::lights_from(&world);
+
+    let cam = cornell_camera(500, 50);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn cornell_leaves(integrator: Integrator) {
     let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+    world.add(Box::new(Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light,
+    )));
+
+    // Leaves drifting under the light, each a single quad cut out along the
+    // alpha channel of its texture, shadows included.
+    let cards = [
+        (
+            Point3::new(420.0, 200.0, 150.0),
+            Vec3::new(-150.0, 0.0, 0.0),
+            Vec3::new(0.0, 80.0, 150.0),
+        ),
+        (
+            Point3::new(260.0, 320.0, 250.0),
+            Vec3::new(-130.0, 0.0, 40.0),
+            Vec3::new(-20.0, 60.0, 140.0),
+        ),
+        (
+            Point3::new(330.0, 120.0, 80.0),
+            Vec3::new(-110.0, 0.0, -30.0),
+            Vec3::new(30.0, 90.0, 110.0),
+        ),
+    ];
+    for (q, u, v) in cards {
+        let leaf = Cutout::new(
+            Box::new(Lambertian::new(Box::new(ImageTexture::new("leaf.png")))),
+            Box::new(ImageTexture::new("leaf.png").alpha()),
+        );
+        world.add(Box::new(Quad::new(q, u, v, Box::new(leaf))));
+    }
+
+    let lights = HittableList::lights_from(&world);
+
+    let cam = cornell_camera(500, 50);
 
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+// The red, green and white walls of the Cornell box, open towards -z and
+// without its light.
+fn cornell_walls(world: &mut HittableList) {
     let red = Box::new(Lambertian::from_color(Color::new(0.65, 0.05, 0.05)));
     let white1 = Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
     let white2 = Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
     let white3 = Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
     let green = Box::new(Lambertian::from_color(Color::new(0.12, 0.45, 0.15)));
-    let light = Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
 
-    let quad1 = Box::new(Quad::new(
+    world.add(Box::new(Quad::new(
         Point3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         green,
-    ));
+    )));
 
-    let quad2 = Box::new(Quad::new(
+    world.add(Box::new(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         red,
-    ));
-
-    let quad3 = Box::new(Quad::new(
-        Point3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        light,
-    ));
+    )));
 
-    let quad4 = Box::new(Quad::new(
+    world.add(Box::new(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         white1,
-    ));
+    )));
 
-    let quad5 = Box::new(Quad::new(
+    world.add(Box::new(Quad::new(
         Point3::new(555.0, 555.0, 555.0),
         Vec3::new(-555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -555.0),
         white2,
-    ));
+    )));
 
-    let quad6 = Box::new(Quad::new(
+    world.add(Box::new(Quad::new(
         Point3::new(0.0, 0.0, 555.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         white3,
-    ));
+    )));
+}
+
+// The usual square view into the Cornell box through its open side.
+fn cornell_camera(samples_per_pixel: i32, max_depth: i32) -> Camera {
+    let aspect_ratio: f64 = 1.0;
+    let image_width: f64 = 600.0;
+    let background = Color::new(0.0, 0.0, 0.0);
+
+    let vfov = 38;
+    let lookfrom = Point3::new(278.0, 278.0, -800.0);
+    let lookat = Point3::new(278.0, 278.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let defocus_angle = 0.0;
+    let focus_dist = 800.0;
+
+    Camera::new(
+        aspect_ratio,
+        image_width,
+        samples_per_pixel,
+        max_depth,
+        background,
+        vfov,
+        lookfrom,
+        lookat,
+        vup,
+        defocus_angle,
+        focus_dist,
+    )
+}
+
+fn cornell_subsurface(integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    // Wound to face up, so it is the back of the light that faces the box.
+    let light = Box::new(
+        DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)).with_sides(EmissionSides::Back),
+    );
+    world.add(Box::new(Quad::new(
+        Point3::new(213.0, 554.0, 227.0),
+        Vec3::new(0.0, 0.0, 105.0),
+        Vec3::new(130.0, 0.0, 0.0),
+        light,
+    )));
+
+    // A jade ball and a block of wax, lit through by light scattering
+    // around under their surfaces.
+    world.add(Box::new(Subsurface::new(
+        Color::new(0.55, 0.85, 0.6),
+        40.0,
+        1.5,
+        |surface| {
+            Box::new(Sphere::new_static(
+                Point3::new(400.0, 90.0, 200.0),
+                90.0,
+                surface(),
+            ))
+        },
+    )));
+
+    world.add(Box::new(Subsurface::new(
+        Color::new(0.9, 0.8, 0.6),
+        25.0,
+        1.4,
+        |surface| {
+            let wax = quad::make_box(
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(150.0, 250.0, 150.0),
+                surface,
+            );
+            let wax = Box::new(RotateY::new(wax, 20.0));
+            Box::new(Translate::new(wax, Vec3::new(80.0, 0.0, 260.0)))
+        },
+    )));
+
+    let lights = HittableList::lights_from(&world);
+
+    // Random walks under the surfaces take many steps.
+    let cam = cornell_camera(100, 200);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn cornell_spotlight(integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
 
     let box1 = quad::make_box(
         Point3::new(0.0, 0.0, 0.0),
         Point3::new(165.0, 330.0, 165.0),
         || Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
-        // || Box::new(Metal::new(Color::new(0.73, 0.73, 0.73), 0.0)),
     );
     let box1 = Box::new(RotateY::new(box1, 15.0));
     let box1 = Box::new(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
+    world.add(box1);
 
-    let box2 = quad::make_box(
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(190.0, 90.0, 190.0),
+        90.0,
+        Box::new(Metal::new(Color::new(0.8, 0.85, 0.88), 0.05)),
+    )));
+
+    // No area lights: a spotlight on the sphere, a warm bulb in the back
+    // corner and cool light falling in through the open front of the box.
+    let mut lights = HittableList::lights_from(&world);
+    lights.add_light(Box::new(SpotLight::new(
+        Point3::new(278.0, 545.0, 150.0),
+        Vec3::new(-0.2, -1.0, 0.1),
+        Color::new(400_000.0, 400_000.0, 360_000.0),
+        15.0,
+        25.0,
+    )));
+    lights.add_light(Box::new(PointLight::from_power(
+        Point3::new(80.0, 450.0, 480.0),
+        Color::new(1.0, 0.7, 0.4),
+        300_000.0,
+    )));
+    lights.add_light(Box::new(DirectionalLight::new(
+        Vec3::new(-0.3, 0.5, -1.0),
+        Color::new(0.4, 0.5, 0.8),
+    )));
+
+    let cam = cornell_camera(100, 50);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn cornell_many_lights(integrator: Integrator) {
+    let mut world = HittableList::new();
+    cornell_walls(&mut world);
+
+    let box1 = quad::make_box(
         Point3::new(0.0, 0.0, 0.0),
-        Point3::new(165.0, 165.0, 165.0),
+        Point3::new(165.0, 330.0, 165.0),
         || Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
-        // || Box::new(Metal::new(Color::new(0.73, 0.73, 0.73), 0.0)),
     );
-    let box2 = Box::new(RotateY::new(box2, -18.0));
-    let box2 = Box::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
+    let box1 = Box::new(RotateY::new(box1, 15.0));
+    let box1 = Box::new(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
+    world.add(box1);
 
-    // let glass = Box::new(Dielectric::new(1.5));
-    // let sphere = Box::new(Sphere::new_static(
-    //     Point3::new(190.0, 90.0, 190.0),
-    //     90.0,
-    //     glass,
-    // ));
+    // A grid of panels on the ceiling and a row of glowing marbles on the
+    // floor, from warm incandescent to cold bluish white, each lighting
+    // mostly what is near it.
+    let kelvins = [2700.0, 4000.0, 6500.0, 10_000.0];
+    for i in 0..4 {
+        for j in 0..4 {
+            let kelvin = kelvins[(i + j) % kelvins.len()];
+            world.add(Box::new(Quad::new(
+                Point3::new(147.5 + 100.0 * i as f64, 554.0, 147.5 + 100.0 * j as f64),
+                Vec3::new(-40.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -40.0),
+                Box::new(DiffuseLight::from_blackbody(kelvin).with_power(20_000.0)),
+            )));
+        }
+    }
+    for i in 0..6 {
+        let kelvin = kelvins[i % kelvins.len()];
+        world.add(Box::new(Sphere::new_static(
+            Point3::new(70.0 + 80.0 * i as f64, 15.0, 90.0),
+            15.0,
+            Box::new(DiffuseLight::from_blackbody(kelvin).with_radiance(2_000.0)),
+        )));
+    }
+
+    let lights = LightBVH::new(HittableList::lights_from(&world));
+
+    let cam = cornell_camera(100, 50);
+
+    // Render
+
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn environment_map(filename: &str, integrator: Integrator) {
+    let mut world = HittableList::new();
+
+    let ground_material = Box::new(Lambertian::new(Box::new(CheckerTexture::from_colors(
+        0.32,
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ))));
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_material,
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Box::new(Dielectric::new(1.5)),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Box::new(Lambertian::from_color(Color::new(0.4, 0.2, 0.1))),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        Box::new(Conductor::gold()),
+    )));
 
+    // Lit only by the image wrapped around the scene, which rays that miss
+    // everything see too.
     let mut lights = HittableList::new();
-    let hittable_light = Box::new(Quad::new(
-        Point3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        Box::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0))),
+    lights.add_light(Box::new(
+        EnvironmentLight::new(filename)
+            .with_rotation(90.0)
+            .with_intensity(2.0),
     ));
 
-    // let glass2 = Box::new(Dielectric::new(1.5));
-    // let sphere2 = Box::new(Sphere::new_static(
-    //     Point3::new(190.0, 90.0, 190.0),
-    //     90.0,
-    //     glass2,
-    // ));
+    let cam = wide_camera(
+        100,
+        20,
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+    );
 
-    lights.add(hittable_light);
-    // lights.add(sphere2);
+    // Render
 
-    world.add(quad1);
-    world.add(quad2);
-    world.add(quad3);
-    world.add(quad4);
-    world.add(quad5);
-    world.add(quad6);
-    world.add(box1);
-    world.add(box2);
-    // world.add(sphere);
+    cam.render_with(integrator, &world, &lights);
+}
 
-    let aspect_ratio: f64 = 1.0;
-    let image_width: f64 = 600.0;
-    let samples_per_pixel = 1_000;
+// A 16:9 view over an outdoor scene, with nothing but its lights to show
+// where rays escape.
+fn wide_camera(samples_per_pixel: i32, vfov: i32, lookfrom: Point3, lookat: Point3) -> Camera {
+    let aspect_ratio: f64 = 16.0 / 9.0;
+    let image_width: f64 = 800.0;
     let max_depth = 50;
     let background = Color::new(0.0, 0.0, 0.0);
 
-    let vfov = 38;
-    let lookfrom = Point3::new(278.0, 278.0, -800.0);
-    let lookat = Point3::new(278.0, 278.0, 0.0);
     let vup = Vec3::new(0.0, 1.0, 0.0);
 
     let defocus_angle = 0.0;
-    let focus_dist = 800.0;
+    let focus_dist = 10.0;
 
-    let cam = Camera::new(
+    Camera::new(
         aspect_ratio,
         image_width,
         samples_per_pixel,
@@ -819,10 +1375,147 @@ fn cornell_box() {
         vup,
         defocus_angle,
         focus_dist,
+    )
+}
+
+fn daylight(integrator: Integrator) {
+    let mut world = HittableList::new();
+
+    let ground_albedo = Color::new(0.35, 0.33, 0.3);
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(Lambertian::from_color(ground_albedo)),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Box::new(Dielectric::new(1.5)),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Box::new(Lambertian::from_color(Color::new(0.6, 0.15, 0.1))),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        Box::new(Metal::new(Color::new(0.9, 0.75, 0.45), 0.1)),
+    )));
+
+    let block = quad::make_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.5, 3.0, 1.5),
+        || Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
+    );
+    let block = Box::new(RotateY::new(block, 30.0));
+    let block = Box::new(Translate::new(block, Vec3::new(-2.0, 0.0, -3.5)));
+    world.add(block);
+
+    // A low afternoon sun, casting long shadows towards the camera, with the
+    // sky filling them in blue.
+    let sky = Sky::new(25.0, 210.0, 3.0)
+        .with_ground_albedo(ground_albedo)
+        .with_intensity(0.015);
+    let mut lights = HittableList::new();
+    lights.add_light(Box::new(sky.environment()));
+    lights.add_light(Box::new(sky.sun()));
+
+    let cam = wide_camera(
+        100,
+        25,
+        Point3::new(13.0, 2.5, 3.0),
+        Point3::new(0.0, 1.5, -0.5),
     );
 
     // Render
 
+    cam.render_with(integrator, &world, &lights);
+}
+
+fn gradient_sky() {
+    let mut world = HittableList::new();
+
+    let pertext = Box::new(PerlinNoise::new(4.0));
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Box::new(Lambertian::new(pertext)),
+    )));
+
+    let earth_texture = Box::new(ImageTexture::new("earthmap.jpg"));
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Box::new(Lambertian::new(earth_texture)),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(-1.5, 0.7, 3.0),
+        0.7,
+        Box::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0)),
+    )));
+
+    // Nothing to sample: the sky lights the scene only where bounces
+    // happen to escape into it.
+    let lights = HittableList::new();
+
+    let cam = wide_camera(
+        100,
+        20,
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 1.0, 0.0),
+    )
+    .with_background(Box::new(Gradient::sky()));
+
+    // Render
+
+    cam.render(&world, &lights);
+}
+
+fn studio() {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(-2.2, 0.0, 0.0),
+        1.0,
+        Box::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0)),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(0.0, 0.0, 0.0),
+        1.0,
+        Box::new(Dielectric::new(1.5)),
+    )));
+
+    world.add(Box::new(Sphere::new_static(
+        Point3::new(2.2, 0.0, 0.0),
+        1.0,
+        Box::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73))),
+    )));
+
+    let lights = HittableList::new();
+
+    // The spheres show and are lit by a world map wrapped around them, in
+    // front of a plain dark backdrop.
+    let earth_texture = Box::new(ImageTexture::new("earthmap.jpg"));
+    let cam = wide_camera(
+        100,
+        30,
+        Point3::new(0.0, 1.0, 10.0),
+        Point3::new(0.0, 0.0, 0.0),
+    )
+    .with_visible_background(Box::new(Gradient::new(
+        Color::new(0.02, 0.02, 0.02),
+        Color::new(0.15, 0.15, 0.18),
+    )))
+    .with_lighting_background(Box::new(TextureBackground::new(earth_texture)));
+
+    // Render
+
     cam.render(&world, &lights);
 }
 
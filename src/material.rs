@@ -1,14 +1,19 @@
 use core::f64;
 
-use rand::Rng;
-
-use crate::color::Color;
+use crate::color::{self, Color};
 use crate::hittable::HitRecord;
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
 use crate::pdf::Pdf;
-use crate::pdf::{CosinePdf, SpherePdf};
-use crate::ray::Ray;
+use crate::pdf::{
+    CosinePdf, MicrofacetReflectionPdf, MicrofacetTransmissionPdf, RefractedPdf, SpherePdf,
+    WeightedPdf,
+};
+use crate::ray::{Interior, Ray};
+use crate::sampler;
+use crate::spectrum;
 use crate::texture::{SolidColor, Texture};
-use crate::vec3::{self, Point3};
+use crate::vec3::{self, Point3, Vec3};
 
 pub enum ScatterRecord {
     Pdf {
@@ -17,7 +22,7 @@ pub enum ScatterRecord {
     },
     SkipPdf {
         attenuation: Color,
-        ray: Ray,
+        ray: Box<Ray>,
     },
 }
 
@@ -30,9 +35,78 @@ pub trait Material: Send + Sync {
         Color::new(0.0, 0.0, 0.0)
     }
 
+    // Density with which the `Pdf` that `scatter` returns picks `scattered`.
     fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
         0.0
     }
+
+    // BSDF times the cosine of `scattered` with the normal, which integrators
+    // divide by the pdf they sampled `scattered` with for `Pdf` records.
+    // Delta (`SkipPdf`) lobes can't be evaluated and stay black.
+    fn eval(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // The ray leaving `rec` in `direction`, sampled from `scatter`'s `Pdf`.
+    // Materials bounding a medium override it to enter or leave the medium
+    // when `direction` crosses the surface.
+    fn scattered_ray(&self, r_in: Ray, rec: &HitRecord, direction: Vec3) -> Ray {
+        r_in.continued(rec.p, direction)
+    }
+
+    // Scatters inside a volume rather than off a surface, so no cosine
+    // applies at the hit point.
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+
+    // Spectral mode: what `scatter`'s `SkipPdf` attenuation, or `eval` for
+    // `Pdf` records, is at wavelength `lambda` (nm), for materials described
+    // by a spectrum. `None` upsamples the RGB value instead.
+    fn spectral_attenuation(
+        &self,
+        _r_in: Ray,
+        _rec: &HitRecord,
+        _scattered: Ray,
+        _lambda: f64,
+    ) -> Option<f64> {
+        None
+    }
+
+    // The scattered direction depends on `r_in.wavelength()`, so in spectral
+    // mode only the hero wavelength can follow it.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // Whether `emitted` can be non-zero anywhere, so that geometry with this
+    // material belongs in light lists.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Spectral mode: what `emitted` is at wavelength `lambda` (nm), for
+    // emitters described by a spectrum. `None` upsamples the RGB value.
+    fn spectral_emission(&self, _r_in: Ray, _rec: &HitRecord, _lambda: f64) -> Option<f64> {
+        None
+    }
+
+    // Sides `emitted` can be non-zero on, for integrators that start paths on
+    // lights.
+    fn emission_sides(&self) -> EmissionSides {
+        EmissionSides::Front
+    }
+
+    // Tells an emitter the area of the surface it was put on, for those
+    // given a total power rather than a radiance. Shapes call it as they take
+    // their material.
+    fn set_emitter_area(&mut self, _area: f64) {}
+
+    // Fraction of the time the surface is there at all; rays pass straight
+    // through the rest (see `HitRecord::is_opaque`).
+    fn opacity(&self, _rec: &HitRecord) -> f64 {
+        1.0
+    }
 }
 
 pub struct Lambertian {
@@ -70,6 +144,75 @@ impl Material for Lambertian {
             cos_theta / f64::consts::PI
         }
     }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.tex.value(rec.u, rec.v, rec.p) * self.scattering_pdf(r_in, rec, scattered)
+    }
+}
+
+// Rough diffuse surfaces such as clay or plaster, which unlike `Lambertian`
+// scatter back towards the light at grazing angles. This is Fujii's improved
+// Oren-Nayar model, with `roughness` the slope deviation of the facets.
+pub struct OrenNayar {
+    tex: Box<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(tex: Box<dyn Texture>, roughness: f64) -> Self {
+        let a = 1.0 / (f64::consts::PI + (f64::consts::FRAC_PI_2 - 2.0 / 3.0) * roughness);
+
+        Self {
+            tex,
+            a,
+            b: roughness * a,
+        }
+    }
+
+    pub fn from_color(albedo: Color, roughness: f64) -> Self {
+        Self::new(Box::new(SolidColor::new(albedo)), roughness)
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, _r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let attenuation = self.tex.value(rec.u, rec.v, rec.p);
+        let pdf_ptr = Box::new(CosinePdf::new(rec.normal));
+        Some(ScatterRecord::Pdf {
+            attenuation,
+            pdf_ptr,
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let cos_theta = vec3::dot(rec.normal, vec3::unit_vector(scattered.direction()));
+
+        if cos_theta < 0.0 {
+            0.0
+        } else {
+            cos_theta / f64::consts::PI
+        }
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let wo = -vec3::unit_vector(r_in.direction());
+        let wi = vec3::unit_vector(scattered.direction());
+        let cos_theta_o = vec3::dot(rec.normal, wo);
+        let cos_theta_i = vec3::dot(rec.normal, wi);
+        if cos_theta_i <= 0.0 || cos_theta_o <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let s = vec3::dot(wi, wo) - cos_theta_i * cos_theta_o;
+        let t = if s > 0.0 {
+            cos_theta_i.max(cos_theta_o)
+        } else {
+            1.0
+        };
+
+        self.tex.value(rec.u, rec.v, rec.p) * (self.a + self.b * s / t) * cos_theta_i
+    }
 }
 
 pub struct Metal {
@@ -88,125 +231,1852 @@ impl Material for Metal {
         let reflected = vec3::reflect(r_in.direction(), rec.normal);
         let reflected = vec3::unit_vector(reflected) + (self.fuzz * vec3::random_unit_vector());
         let attenuation = self.albedo;
-        let ray = Ray::new(rec.p, reflected, r_in.time());
+        let ray = r_in.continued(rec.p, reflected);
 
-        Some(ScatterRecord::SkipPdf { attenuation, ray })
+        Some(ScatterRecord::SkipPdf {
+            attenuation,
+            ray: Box::new(ray),
+        })
     }
 }
 
+// Smooth glass. Light travelling inside is absorbed according to the
+// Beer-Lambert law with the given absorption coefficient per unit distance.
+// Overlapping dielectrics, such as liquid in a glass, are resolved by
+// priority (Schmidt and Budge 2002): inside a higher priority medium a
+// boundary is ignored, otherwise the indices on both sides are used.
 pub struct Dielectric {
     refraction_index: f64,
+    absorption: Color,
+    priority: i32,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    pub fn with_priority(self, priority: i32) -> Self {
+        Self { priority, ..self }
+    }
+
+    // Whether `r` is travelling through this dielectric.
+    pub fn contains(&self, r: &Ray) -> bool {
+        r.medium().is_some_and(|m| m.id == self.interior().id)
+    }
+
+    fn interior(&self) -> Interior {
+        Interior {
+            id: self as *const Self as usize,
+            priority: self.priority,
+            refraction_index: self.refraction_index,
+            absorption: self.absorption,
+        }
     }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // Absorption along the way is left to the integrators, through
+        // `Ray::transmittance`, since the segment may end on anything.
         let attenuation = Color::new(1.0, 1.0, 1.0);
-        let ri = if rec.front_face {
-            1.0 / self.refraction_index
-        } else {
-            self.refraction_index
-        };
 
-        let unit_direction = vec3::unit_vector(r_in.direction());
-        let cos_theta = vec3::dot(-unit_direction, rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let cannot_refract = ri * sin_theta > 1.0;
+        let (beyond, ri) = crossing(r_in, rec, self.interior());
+        let Some(ri) = ri else {
+            let ray = beyond.continued(rec.p, r_in.direction());
 
-        let direction = if cannot_refract || reflectance(cos_theta, ri) > rand::rng().random() {
-            vec3::reflect(unit_direction, rec.normal)
-        } else {
-            vec3::refract(unit_direction, rec.normal, ri)
+            return Some(ScatterRecord::SkipPdf {
+                attenuation,
+                ray: Box::new(ray),
+            });
         };
 
-        let ray = Ray::new(rec.p, direction, r_in.time());
+        let (ray, refracted) = refract_or_reflect(r_in, rec, ri);
+        let ray = if refracted {
+            beyond.continued(ray.origin(), ray.direction())
+        } else {
+            ray
+        };
 
-        Some(ScatterRecord::SkipPdf { attenuation, ray })
+        Some(ScatterRecord::SkipPdf {
+            attenuation,
+            ray: Box::new(ray),
+        })
     }
 }
 
-fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
-    let r0 = (1.0 - refraction_index) / (1.0 + refraction_index).powi(2);
+// How `r_in` crosses the boundary of the medium `this` at `rec`, given the
+// media it is already in: the ray beyond, having entered or left `this`, and
+// the ratio of the index on the incoming side to the one on the other. The
+// ratio is `None` when a higher priority medium the ray is in hides the
+// boundary, which rays then pass straight through.
+fn crossing(r_in: Ray, rec: &HitRecord, this: Interior) -> (Ray, Option<f64>) {
+    let current = r_in.medium();
 
-    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
-}
+    let (beyond, ri) = if rec.front_face {
+        let beyond = r_in.entering(this);
+        let outside = current.map_or(1.0, |m| m.refraction_index);
 
-pub struct DiffuseLight {
-    tex: Box<dyn Texture>,
+        (beyond, outside / this.refraction_index)
+    } else {
+        let beyond = r_in.leaving(this.id);
+        let outside = beyond.medium().map_or(1.0, |m| m.refraction_index);
+
+        (beyond, this.refraction_index / outside)
+    };
+
+    let shadowed = current.is_some_and(|m| m.id != this.id && m.priority > this.priority);
+
+    (beyond, (!shadowed).then_some(ri))
 }
 
-impl DiffuseLight {
-    pub fn new(tex: Box<dyn Texture>) -> Self {
-        Self { tex }
+// Picks reflection or refraction at a smooth dielectric boundary in
+// proportion to the Fresnel reflectance, with `ri` the ratio of the index on
+// the incoming side to the one on the other. Also tells whether it refracted.
+fn refract_or_reflect(r_in: Ray, rec: &HitRecord, ri: f64) -> (Ray, bool) {
+    let unit_direction = vec3::unit_vector(r_in.direction());
+    let cos_theta = vec3::dot(-unit_direction, rec.normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let cannot_refract = ri * sin_theta > 1.0;
+
+    if cannot_refract || fresnel_dielectric(cos_theta, ri) > sampler::random_double() {
+        let direction = vec3::reflect(unit_direction, rec.normal);
+
+        (r_in.continued(rec.p, direction), false)
+    } else {
+        let direction = vec3::refract(unit_direction, rec.normal, ri);
+
+        (r_in.continued(rec.p, direction), true)
     }
+}
+
+// Frosted glass: a dielectric boundary made of GGX microfacets that each
+// reflect or refract (Walter et al. 2007). Absorbs and nests with other
+// media like `Dielectric`.
+pub struct RoughDielectric {
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    absorption: Color,
+    priority: i32,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> Self {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
 
-    pub fn from_color(albedo: Color) -> Self {
         Self {
-            tex: Box::new(SolidColor::new(albedo)),
+            refraction_index,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
         }
     }
+
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    pub fn with_priority(self, priority: i32) -> Self {
+        Self { priority, ..self }
+    }
+
+    fn interior(&self) -> Interior {
+        Interior {
+            id: self as *const Self as usize,
+            priority: self.priority,
+            refraction_index: self.refraction_index,
+            absorption: self.absorption,
+        }
+    }
+
+    // Index below the surface over the one above, as seen from `r_in`'s
+    // side, or `None` where a higher priority medium hides the boundary.
+    fn eta(&self, r_in: Ray, rec: &HitRecord) -> Option<f64> {
+        let (_, ri) = crossing(r_in, rec, self.interior());
+
+        ri.map(|ri| 1.0 / ri)
+    }
+
+    fn pdf(&self, r_in: Ray, rec: &HitRecord, eta: f64) -> MicrofacetTransmissionPdf {
+        MicrofacetTransmissionPdf::new(
+            Onb::new(rec.normal),
+            -vec3::unit_vector(r_in.direction()),
+            eta,
+            self.distribution,
+        )
+    }
 }
 
-impl Material for DiffuseLight {
-    fn emitted(&self, _r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
-        if !rec.front_face {
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let attenuation = Color::new(1.0, 1.0, 1.0);
+
+        let (beyond, ri) = crossing(r_in, rec, self.interior());
+        let Some(ri) = ri else {
+            let ray = beyond.continued(rec.p, r_in.direction());
+
+            return Some(ScatterRecord::SkipPdf {
+                attenuation,
+                ray: Box::new(ray),
+            });
+        };
+
+        Some(ScatterRecord::Pdf {
+            attenuation,
+            pdf_ptr: Box::new(self.pdf(r_in, rec, 1.0 / ri)),
+        })
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        match self.eta(r_in, rec) {
+            Some(eta) => self.pdf(r_in, rec, eta).value(scattered.direction()),
+            None => 0.0,
+        }
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let Some(eta) = self.eta(r_in, rec) else {
             return Color::new(0.0, 0.0, 0.0);
-        } else {
-            self.tex.value(u, v, p)
+        };
+
+        let uvw = Onb::new(rec.normal);
+        let wo = uvw.to_local(-vec3::unit_vector(r_in.direction()));
+        let wi = uvw.to_local(vec3::unit_vector(scattered.direction()));
+        let f = rough_dielectric_eval(&self.distribution, wo, wi, eta);
+
+        Color::new(f, f, f)
+    }
+
+    fn scattered_ray(&self, r_in: Ray, rec: &HitRecord, direction: Vec3) -> Ray {
+        if vec3::dot(direction, rec.normal) >= 0.0 {
+            return r_in.continued(rec.p, direction);
         }
+
+        let (beyond, _) = crossing(r_in, rec, self.interior());
+
+        beyond.continued(rec.p, direction)
     }
 }
 
-pub struct Isotropic {
-    tex: Box<dyn Texture>,
-    emit: bool,
+// BSDF times cosine of a rough dielectric boundary for local directions,
+// with `eta` the index below the surface over the one above.
+fn rough_dielectric_eval(distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+    let Some(wm) = TrowbridgeReitz::half_vector(wo, wi, eta) else {
+        return 0.0;
+    };
+
+    let d = distribution.d(wm);
+    let g = distribution.g(wo, wi);
+    let r = fresnel_dielectric(vec3::dot(wo, wm), 1.0 / eta);
+
+    if wi.z() > 0.0 {
+        d * g * r / (4.0 * wo.z().abs())
+    } else {
+        // Radiance is compressed into a smaller solid angle on entering
+        // the denser medium, hence the division by eta squared.
+        let denom = vec3::dot(wi, wm) + vec3::dot(wo, wm) / eta;
+        d * g * (1.0 - r) * (vec3::dot(wi, wm) * vec3::dot(wo, wm)).abs()
+            / (wo.z().abs() * denom * denom * eta * eta)
+    }
 }
 
-impl Isotropic {
-    pub fn from_color(albedo: Color) -> Self {
+// An "uber" material after Burley's principled BSDF (2012, 2015): a diffuse
+// base with retro-reflection and sheen, a GGX specular layer tinted from
+// the dielectric specular towards the base color by `metallic`, a rough
+// dielectric transmission lobe and a clearcoat on top. Every parameter is a
+// texture; scalar parameters read the average of its channels.
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    specular: Box<dyn Texture>,
+    specular_tint: Box<dyn Texture>,
+    sheen: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+    refraction_index: Box<dyn Texture>,
+    anisotropy: Box<dyn Texture>,
+}
+
+impl Principled {
+    pub fn new(base_color: Box<dyn Texture>) -> Self {
+        let value = |v: f64| -> Box<dyn Texture> { Box::new(SolidColor::from_value(v)) };
+
         Self {
-            tex: Box::new(SolidColor::new(albedo)),
-            emit: false,
+            base_color,
+            metallic: value(0.0),
+            roughness: value(0.5),
+            specular: value(0.5),
+            specular_tint: value(0.0),
+            sheen: value(0.0),
+            clearcoat: value(0.0),
+            transmission: value(0.0),
+            refraction_index: value(1.5),
+            anisotropy: value(0.0),
         }
     }
 
-    pub fn from_texture(tex: Box<dyn Texture>) -> Self {
-        Self { tex, emit: false }
+    pub fn from_color(base_color: Color) -> Self {
+        Self::new(Box::new(SolidColor::new(base_color)))
     }
 
-    pub fn from_color_emit(albedo: Color) -> Self {
+    pub fn with_metallic(self, metallic: Box<dyn Texture>) -> Self {
+        Self { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: Box<dyn Texture>) -> Self {
+        Self { roughness, ..self }
+    }
+
+    pub fn with_specular(self, specular: Box<dyn Texture>) -> Self {
+        Self { specular, ..self }
+    }
+
+    pub fn with_specular_tint(self, specular_tint: Box<dyn Texture>) -> Self {
         Self {
-            tex: Box::new(SolidColor::new(albedo)),
-            emit: true,
+            specular_tint,
+            ..self
+        }
+    }
+
+    pub fn with_sheen(self, sheen: Box<dyn Texture>) -> Self {
+        Self { sheen, ..self }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: Box<dyn Texture>) -> Self {
+        Self { clearcoat, ..self }
+    }
+
+    pub fn with_transmission(self, transmission: Box<dyn Texture>) -> Self {
+        Self {
+            transmission,
+            ..self
+        }
+    }
+
+    pub fn with_refraction_index(self, refraction_index: Box<dyn Texture>) -> Self {
+        Self {
+            refraction_index,
+            ..self
+        }
+    }
+
+    pub fn with_anisotropy(self, anisotropy: Box<dyn Texture>) -> Self {
+        Self { anisotropy, ..self }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let scalar = |tex: &dyn Texture| {
+            let c = tex.value(rec.u, rec.v, rec.p);
+            (c.x() + c.y() + c.z()) / 3.0
+        };
+
+        let base_color = self.base_color.value(rec.u, rec.v, rec.p);
+        let metallic = scalar(self.metallic.as_ref()).clamp(0.0, 1.0);
+        let roughness = scalar(self.roughness.as_ref()).clamp(0.0, 1.0);
+        let specular = scalar(self.specular.as_ref()).max(0.0);
+        let specular_tint = scalar(self.specular_tint.as_ref()).clamp(0.0, 1.0);
+        let transmission = scalar(self.transmission.as_ref()).clamp(0.0, 1.0);
+        let refraction_index = scalar(self.refraction_index.as_ref());
+        let anisotropy = scalar(self.anisotropy.as_ref()).clamp(0.0, 1.0);
+
+        let luminance = color::luminance(base_color);
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        };
+        let white = Color::new(1.0, 1.0, 1.0);
+        let dielectric_f0 =
+            0.08 * specular * ((1.0 - specular_tint) * white + specular_tint * tint);
+
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+
+        PrincipledLobes {
+            base_color,
+            roughness,
+            f0: (1.0 - metallic) * dielectric_f0 + metallic * base_color,
+            sheen: scalar(self.sheen.as_ref()).max(0.0),
+            clearcoat: scalar(self.clearcoat.as_ref()).max(0.0),
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            transmission_weight: (1.0 - metallic) * transmission,
+            eta: if rec.front_face {
+                refraction_index
+            } else {
+                1.0 / refraction_index
+            },
+            front_face: rec.front_face,
+            distribution: TrowbridgeReitz::new(alpha / aspect, alpha * aspect),
+            clearcoat_distribution: TrowbridgeReitz::new(0.01, 0.01),
         }
     }
 }
 
-impl Material for Isotropic {
-    fn scatter(&self, _r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let attenuation = self.tex.value(rec.u, rec.v, rec.p);
-        let pdf_ptr = Box::new(SpherePdf {});
+// The principled BSDF's parameters at one point.
+struct PrincipledLobes {
+    base_color: Color,
+    roughness: f64,
+    f0: Color,
+    sheen: f64,
+    clearcoat: f64,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    eta: f64,
+    front_face: bool,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+}
+
+impl PrincipledLobes {
+    // Light only gets inside through the transmission lobe, so from the back
+    // the surface is nothing but the dielectric boundary.
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let white = Color::new(1.0, 1.0, 1.0);
+        if wo.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let transmitted = |weight: f64| {
+            let f = rough_dielectric_eval(&self.distribution, wo, wi, self.eta);
+            let tint = if wi.z() < 0.0 { self.base_color } else { white };
+
+            weight * f * tint
+        };
+
+        if !self.front_face {
+            return transmitted(1.0);
+        }
+
+        let mut f = transmitted(self.transmission_weight);
+
+        if wi.z() > 0.0 {
+            let wh = vec3::unit_vector(wo + wi);
+            let cos_d = vec3::dot(wi, wh);
+
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fl = (1.0 - wi.z()).powi(5);
+            let fv = (1.0 - wo.z()).powi(5);
+            let diffuse = self.base_color / f64::consts::PI
+                * (1.0 + (fd90 - 1.0) * fl)
+                * (1.0 + (fd90 - 1.0) * fv);
+            let sheen = self.sheen * (1.0 - cos_d).powi(5) * white;
+            f += self.diffuse_weight * (diffuse + sheen) * wi.z();
+
+            let specular_weight = 1.0 - self.transmission_weight;
+            let fresnel = schlick(self.f0, vec3::dot(wo, wh));
+            f += specular_weight * self.distribution.d(wh) * self.distribution.g(wo, wi) * fresnel
+                / (4.0 * wo.z());
+
+            let coat = &self.clearcoat_distribution;
+            let fresnel = schlick(0.04 * white, vec3::dot(wo, wh));
+            f += 0.25 * self.clearcoat * coat.d(wh) * coat.g(wo, wi) * fresnel / (4.0 * wo.z());
+        }
+
+        f
+    }
+
+    // Lobes are picked roughly in proportion to how much they reflect.
+    fn pdf(&self, uvw: Onb, wo: Vec3) -> WeightedPdf {
+        let normal = uvw.w();
+        let transmission = Box::new(MicrofacetTransmissionPdf::new(
+            uvw,
+            wo,
+            self.eta,
+            self.distribution,
+        ));
+        if !self.front_face {
+            return WeightedPdf::new(vec![(1.0, transmission)]);
+        }
+
+        let cos_theta = vec3::dot(wo, normal).clamp(0.0, 1.0);
+        let specular_weight = (1.0 - self.transmission_weight)
+            * color::luminance(schlick(self.f0, cos_theta)).max(1e-3);
+        let clearcoat_weight = 0.25
+            * self.clearcoat
+            * color::luminance(schlick(Color::new(0.04, 0.04, 0.04), cos_theta));
+
+        WeightedPdf::new(vec![
+            (
+                self.diffuse_weight * color::luminance(self.base_color).max(self.sheen),
+                Box::new(CosinePdf::new(normal)),
+            ),
+            (
+                specular_weight,
+                Box::new(MicrofacetReflectionPdf::new(uvw, wo, self.distribution)),
+            ),
+            (
+                clearcoat_weight,
+                Box::new(MicrofacetReflectionPdf::new(
+                    uvw,
+                    wo,
+                    self.clearcoat_distribution,
+                )),
+            ),
+            (self.transmission_weight, transmission),
+        ])
+    }
+}
+
+fn schlick(f0: Color, cos_theta: f64) -> Color {
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let lobes = self.lobes(rec);
+        let wo = -vec3::unit_vector(r_in.direction());
 
         Some(ScatterRecord::Pdf {
-            attenuation,
-            pdf_ptr,
+            attenuation: lobes.base_color,
+            pdf_ptr: Box::new(lobes.pdf(rec.shading_frame(), wo)),
         })
     }
 
-    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
-        1.0 / (4.0 * f64::consts::PI)
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let wo = -vec3::unit_vector(r_in.direction());
+
+        self.lobes(rec)
+            .pdf(rec.shading_frame(), wo)
+            .value(scattered.direction())
     }
 
-    fn emitted(&self, _r_in: Ray, _rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
-        if self.emit {
-            self.tex.value(u, v, p)
-        } else {
-            Color::new(0.0, 0.0, 0.0)
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(-vec3::unit_vector(r_in.direction()));
+        let wi = uvw.to_local(vec3::unit_vector(scattered.direction()));
+
+        self.lobes(rec).eval(wo, wi)
+    }
+}
+
+// A dielectric coat over any other material, for car paint or varnished
+// wood. The coat reflects off GGX microfacets (roughness zero makes it
+// smooth); the rest of the light refracts in, is absorbed on its way through
+// the coat, scatters off `base` and refracts back out, losing what the
+// Fresnel reflection at each pass keeps inside. Only single scattering
+// inside the coat is accounted for.
+pub struct Layered {
+    base: Box<dyn Material>,
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    absorption: Color,
+}
+
+impl Layered {
+    pub fn new(base: Box<dyn Material>, refraction_index: f64, roughness: f64) -> Self {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+
+        Self {
+            base,
+            refraction_index,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            absorption: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Absorption coefficient of the coat times its thickness.
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    // The direction inside the coat that `w`, pointing away from the
+    // surface, refracts to.
+    fn inside(&self, w: Vec3, normal: Vec3) -> Vec3 {
+        -vec3::refract(-w, normal, 1.0 / self.refraction_index)
+    }
+
+    fn transmittance(&self, cos_theta_inside: f64) -> Color {
+        let t = |sigma: f64| (-sigma / cos_theta_inside).exp();
+
+        Color::new(
+            t(self.absorption.x()),
+            t(self.absorption.y()),
+            t(self.absorption.z()),
+        )
+    }
+
+    fn coat_pdf(&self, r_in: Ray, rec: &HitRecord) -> MicrofacetReflectionPdf {
+        MicrofacetReflectionPdf::new(
+            Onb::new(rec.normal),
+            -vec3::unit_vector(r_in.direction()),
+            self.distribution,
+        )
+    }
+
+    // The incoming ray as the base sees it, below the coat.
+    fn base_ray(&self, r_in: Ray, rec: &HitRecord) -> Ray {
+        let wo = -vec3::unit_vector(r_in.direction());
+
+        r_in.continued(rec.p, -self.inside(wo, rec.normal))
+    }
+
+    fn pdf(&self, r_in: Ray, rec: &HitRecord, base_pdf: Option<Box<dyn Pdf>>) -> WeightedPdf {
+        let cos_theta = vec3::dot(-vec3::unit_vector(r_in.direction()), rec.normal);
+        let reflectance = fresnel_dielectric(cos_theta, 1.0 / self.refraction_index);
+        let mut pdfs: Vec<(f64, Box<dyn Pdf>)> =
+            vec![(reflectance, Box::new(self.coat_pdf(r_in, rec)))];
+
+        if let Some(base_pdf) = base_pdf {
+            let refracted = RefractedPdf::new(base_pdf, rec.normal, self.refraction_index);
+            pdfs.push((1.0 - reflectance, Box::new(refracted)));
+        }
+
+        WeightedPdf::new(pdfs)
+    }
+}
+
+impl Material for Layered {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec);
+        }
+
+        let normal = rec.normal;
+        let cos_theta = vec3::dot(-vec3::unit_vector(r_in.direction()), normal);
+        let reflectance = fresnel_dielectric(cos_theta, 1.0 / self.refraction_index);
+        let base_ray = self.base_ray(r_in, rec);
+
+        match self.base.scatter(base_ray, rec) {
+            Some(ScatterRecord::Pdf {
+                attenuation,
+                pdf_ptr,
+            }) => Some(ScatterRecord::Pdf {
+                attenuation,
+                pdf_ptr: Box::new(self.pdf(r_in, rec, Some(pdf_ptr))),
+            }),
+            // A delta base can't be evaluated, so the coat and the base are
+            // picked between here instead, weighted by the Fresnel
+            // reflectance.
+            Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+                if sampler::random_double() < reflectance {
+                    let coat_pdf = self.coat_pdf(r_in, rec);
+                    let scattered = r_in.continued(rec.p, coat_pdf.generate()?);
+                    let pdf = coat_pdf.value(scattered.direction());
+                    if pdf <= 0.0 {
+                        return None;
+                    }
+
+                    let attenuation = self.eval(r_in, rec, scattered) / (pdf * reflectance);
+
+                    return Some(ScatterRecord::SkipPdf {
+                        attenuation,
+                        ray: Box::new(scattered),
+                    });
+                }
+
+                let inside = vec3::unit_vector(ray.direction());
+                let cos_theta_inside = vec3::dot(inside, normal);
+                let cos_theta_o = vec3::dot(-vec3::unit_vector(base_ray.direction()), normal);
+                let attenuation = attenuation * self.transmittance(cos_theta_o);
+
+                // Transmitted by the base: carry on into the object.
+                if cos_theta_inside <= 0.0 {
+                    return Some(ScatterRecord::SkipPdf { attenuation, ray });
+                }
+
+                let eta = self.refraction_index;
+                if eta * eta * (1.0 - cos_theta_inside * cos_theta_inside) >= 1.0 {
+                    return None;
+                }
+
+                let outside = vec3::refract(inside, -normal, eta);
+                let transmitted = 1.0 - fresnel_dielectric(cos_theta_inside, eta);
+
+                Some(ScatterRecord::SkipPdf {
+                    attenuation: attenuation * self.transmittance(cos_theta_inside) * transmitted,
+                    ray: Box::new(ray.continued(rec.p, outside)),
+                })
+            }
+            None => Some(ScatterRecord::Pdf {
+                attenuation: Color::new(1.0, 1.0, 1.0),
+                pdf_ptr: Box::new(self.pdf(r_in, rec, None)),
+            }),
+        }
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        if !rec.front_face {
+            return self.base.scattering_pdf(r_in, rec, scattered);
+        }
+
+        let base_pdf = match self.base.scatter(self.base_ray(r_in, rec), rec) {
+            Some(ScatterRecord::Pdf { pdf_ptr, .. }) => Some(pdf_ptr),
+            _ => None,
+        };
+
+        self.pdf(r_in, rec, base_pdf).value(scattered.direction())
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        if !rec.front_face {
+            return self.base.eval(r_in, rec, scattered);
+        }
+
+        let normal = rec.normal;
+        let wo = -vec3::unit_vector(r_in.direction());
+        let wi = vec3::unit_vector(scattered.direction());
+        let cos_theta_o = vec3::dot(wo, normal);
+        let cos_theta_i = vec3::dot(wi, normal);
+        if cos_theta_o <= 0.0 || cos_theta_i <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let eta = self.refraction_index;
+        let uvw = Onb::new(normal);
+        let (wo_local, wi_local) = (uvw.to_local(wo), uvw.to_local(wi));
+        let wm = vec3::unit_vector(wo_local + wi_local);
+        let coat = self.distribution.d(wm)
+            * self.distribution.g(wo_local, wi_local)
+            * fresnel_dielectric(vec3::dot(wo_local, wm), 1.0 / eta)
+            / (4.0 * cos_theta_o);
+
+        let wo_inside = self.inside(wo, normal);
+        let wi_inside = self.inside(wi, normal);
+        let cos_theta_o_inside = vec3::dot(wo_inside, normal);
+        let cos_theta_i_inside = vec3::dot(wi_inside, normal);
+        let base = self.base.eval(
+            r_in.continued(rec.p, -wo_inside),
+            rec,
+            scattered.continued(rec.p, wi_inside),
+        );
+
+        // `base` holds the cosine inside the coat; swapping it for the one
+        // outside and dividing by eta squared accounts for the solid angle
+        // compressing on the way in and spreading on the way out.
+        let transmitted = (1.0 - fresnel_dielectric(cos_theta_o, 1.0 / eta))
+            * (1.0 - fresnel_dielectric(cos_theta_i, 1.0 / eta))
+            * cos_theta_i
+            / (cos_theta_i_inside * eta * eta);
+        let absorbed =
+            self.transmittance(cos_theta_o_inside) * self.transmittance(cos_theta_i_inside);
+
+        Color::new(coat, coat, coat) + transmitted * absorbed * base
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
+
+// Two materials on one surface, like rust patches on metal through a noise
+// mask: `mask` is the probability of `b` over `a`. The choice comes from a
+// hash of the hit point instead of a random draw, so that `scatter`,
+// `emitted`, `eval` and `scattering_pdf` all agree on the material there
+// (after PBRT-v4's MixMaterial).
+pub struct MixMaterial {
+    a: Box<dyn Material>,
+    b: Box<dyn Material>,
+    mask: Box<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Box<dyn Material>, b: Box<dyn Material>, mask: Box<dyn Texture>) -> Self {
+        Self { a, b, mask }
+    }
+
+    fn choose(&self, rec: &HitRecord) -> &dyn Material {
+        let c = self.mask.value(rec.u, rec.v, rec.p);
+        let amount = ((c.x() + c.y() + c.z()) / 3.0).clamp(0.0, 1.0);
+        if amount <= 0.0 {
+            return self.a.as_ref();
+        } else if amount >= 1.0 {
+            return self.b.as_ref();
+        }
+
+        if sampler::hash_point(rec.p) < amount {
+            self.b.as_ref()
+        } else {
+            self.a.as_ref()
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.choose(rec).scatter(r_in, rec)
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.choose(rec).emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.choose(rec).scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.choose(rec).eval(r_in, rec, scattered)
+    }
+
+    fn scattered_ray(&self, r_in: Ray, rec: &HitRecord, direction: Vec3) -> Ray {
+        self.choose(rec).scattered_ray(r_in, rec, direction)
+    }
+
+    fn spectral_attenuation(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        scattered: Ray,
+        lambda: f64,
+    ) -> Option<f64> {
+        self.choose(rec)
+            .spectral_attenuation(r_in, rec, scattered, lambda)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.choose(rec).opacity(rec)
+    }
+
+    fn spectral_emission(&self, r_in: Ray, rec: &HitRecord, lambda: f64) -> Option<f64> {
+        self.choose(rec).spectral_emission(r_in, rec, lambda)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+
+    fn set_emitter_area(&mut self, area: f64) {
+        self.a.set_emitter_area(area);
+        self.b.set_emitter_area(area);
+    }
+
+    fn is_volumetric(&self) -> bool {
+        self.a.is_volumetric() || self.b.is_volumetric()
+    }
+
+    // The sides lit by either part; one that emits nothing lights none.
+    fn emission_sides(&self) -> EmissionSides {
+        match (self.a.is_emissive(), self.b.is_emissive()) {
+            (true, false) => self.a.emission_sides(),
+            (false, true) => self.b.emission_sides(),
+            _ => self.a.emission_sides().union(self.b.emission_sides()),
+        }
+    }
+}
+
+// Where the shading normal of `NormalMapped` comes from.
+pub enum NormalPerturbation {
+    // Tangent-space normals encoded as colors, x and y along the tangent and
+    // bitangent and z along the surface normal.
+    NormalMap(Box<dyn Texture>),
+    // A height field displacing the surface along its normal, `scale` units
+    // per unit of texture value.
+    Bump {
+        height: Box<dyn Texture>,
+        scale: f64,
+    },
+}
+
+// Surface detail without extra geometry: `inner` is shaded with a normal
+// perturbed by a normal or bump map. Light the shading normal would send to
+// the other side of the geometric surface than the geometric normal does is
+// dropped, so that nothing leaks through.
+pub struct NormalMapped {
+    inner: Box<dyn Material>,
+    perturbation: NormalPerturbation,
+}
+
+impl NormalMapped {
+    pub fn from_normal_map(inner: Box<dyn Material>, map: Box<dyn Texture>) -> Self {
+        Self {
+            inner,
+            perturbation: NormalPerturbation::NormalMap(map),
+        }
+    }
+
+    pub fn from_bump(inner: Box<dyn Material>, height: Box<dyn Texture>, scale: f64) -> Self {
+        Self {
+            inner,
+            perturbation: NormalPerturbation::Bump { height, scale },
+        }
+    }
+
+    // The perturbed normal on the outward side of the surface.
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let normal = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+
+        // Surfaces without a parameterization get an arbitrary frame.
+        let (tangent, bitangent) = if rec.tangent.length_squared() > 0.0 {
+            (rec.tangent, rec.bitangent)
+        } else {
+            let uvw = Onb::new(normal);
+            (uvw.u(), uvw.v())
+        };
+
+        match &self.perturbation {
+            NormalPerturbation::NormalMap(map) => {
+                let c = 2.0 * map.value(rec.u, rec.v, rec.p) - Color::new(1.0, 1.0, 1.0);
+                let t = vec3::unit_vector(tangent - vec3::dot(tangent, normal) * normal);
+                let b = vec3::cross(normal, t);
+                let b = if vec3::dot(b, bitangent) < 0.0 { -b } else { b };
+
+                vec3::unit_vector(c.x() * t + c.y() * b + c.z() * normal)
+            }
+            NormalPerturbation::Bump { height, scale } => {
+                let h = |u: f64, v: f64, p: Point3| {
+                    let c = height.value(u, v, p);
+                    scale * (c.x() + c.y() + c.z()) / 3.0
+                };
+
+                let delta = 1e-3;
+                let h0 = h(rec.u, rec.v, rec.p);
+                let dh_du = (h(rec.u + delta, rec.v, rec.p + delta * tangent) - h0) / delta;
+                let dh_dv = (h(rec.u, rec.v + delta, rec.p + delta * bitangent) - h0) / delta;
+
+                let n = vec3::cross(tangent + dh_du * normal, bitangent + dh_dv * normal);
+                let n = vec3::unit_vector(n);
+                if vec3::dot(n, normal) < 0.0 { -n } else { n }
+            }
+        }
+    }
+
+    // `rec` as `inner` sees it, unless the perturbed normal faces away from
+    // where the ray came from.
+    fn shade<'a>(&self, r_in: Ray, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        let normal = self.shading_normal(rec);
+        let normal = if rec.front_face { normal } else { -normal };
+
+        if vec3::dot(r_in.direction(), normal) >= 0.0 {
+            return *rec;
+        }
+
+        HitRecord { normal, ..*rec }
+    }
+
+    fn leaks(rec: &HitRecord, shaded: &HitRecord, scattered: Ray) -> bool {
+        let geometric = vec3::dot(scattered.direction(), rec.normal);
+        let shading = vec3::dot(scattered.direction(), shaded.normal);
+
+        geometric * shading < 0.0
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let shaded = self.shade(r_in, rec);
+
+        match self.inner.scatter(r_in, &shaded)? {
+            ScatterRecord::SkipPdf { ray, .. } if Self::leaks(rec, &shaded, *ray) => None,
+            srec => Some(srec),
+        }
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.inner.emitted(r_in, &self.shade(r_in, rec), u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.inner
+            .scattering_pdf(r_in, &self.shade(r_in, rec), scattered)
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let shaded = self.shade(r_in, rec);
+        if Self::leaks(rec, &shaded, scattered) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        self.inner.eval(r_in, &shaded, scattered)
+    }
+
+    // Whether the ray crosses the surface is up to the geometric normal.
+    fn scattered_ray(&self, r_in: Ray, rec: &HitRecord, direction: Vec3) -> Ray {
+        self.inner.scattered_ray(r_in, rec, direction)
+    }
+
+    fn is_volumetric(&self) -> bool {
+        self.inner.is_volumetric()
+    }
+
+    fn spectral_attenuation(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        scattered: Ray,
+        lambda: f64,
+    ) -> Option<f64> {
+        let shaded = self.shade(r_in, rec);
+        if Self::leaks(rec, &shaded, scattered) {
+            return Some(0.0);
+        }
+
+        self.inner
+            .spectral_attenuation(r_in, &shaded, scattered, lambda)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.inner.is_dispersive()
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.inner.opacity(rec)
+    }
+
+    fn emission_sides(&self) -> EmissionSides {
+        self.inner.emission_sides()
+    }
+
+    fn spectral_emission(&self, r_in: Ray, rec: &HitRecord, lambda: f64) -> Option<f64> {
+        self.inner.spectral_emission(r_in, rec, lambda)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.inner.is_emissive()
+    }
+
+    fn set_emitter_area(&mut self, area: f64) {
+        self.inner.set_emitter_area(area);
+    }
+}
+
+// Cuts `inner` out along a mask, such as `ImageTexture::alpha`, for foliage
+// cards, fences and decals: where the mask is black the surface is missing,
+// and grey parts are hit that fraction of the time.
+pub struct Cutout {
+    inner: Box<dyn Material>,
+    mask: Box<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(inner: Box<dyn Material>, mask: Box<dyn Texture>) -> Self {
+        Self { inner, mask }
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.inner.scatter(r_in, rec)
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.inner.emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.inner.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.inner.eval(r_in, rec, scattered)
+    }
+
+    fn scattered_ray(&self, r_in: Ray, rec: &HitRecord, direction: Vec3) -> Ray {
+        self.inner.scattered_ray(r_in, rec, direction)
+    }
+
+    fn is_volumetric(&self) -> bool {
+        self.inner.is_volumetric()
+    }
+
+    fn spectral_attenuation(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        scattered: Ray,
+        lambda: f64,
+    ) -> Option<f64> {
+        self.inner
+            .spectral_attenuation(r_in, rec, scattered, lambda)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.inner.is_dispersive()
+    }
+
+    fn emission_sides(&self) -> EmissionSides {
+        self.inner.emission_sides()
+    }
+
+    fn spectral_emission(&self, r_in: Ray, rec: &HitRecord, lambda: f64) -> Option<f64> {
+        self.inner.spectral_emission(r_in, rec, lambda)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.inner.is_emissive()
+    }
+
+    fn set_emitter_area(&mut self, area: f64) {
+        self.inner.set_emitter_area(area);
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let c = self.mask.value(rec.u, rec.v, rec.p);
+
+        ((c.x() + c.y() + c.z()) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+// Index of refraction as a function of wavelength, with the wavelength in
+// micrometres as the formulas are usually tabulated.
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub fn refraction_index(&self, wavelength_nm: f64) -> f64 {
+        let l = wavelength_nm / 1000.0;
+        let l2 = l * l;
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+
+                n2.sqrt()
+            }
+        }
+    }
+}
+
+// Wavelengths standing in for the RGB channels when no spectral wavelength
+// is available.
+const CHANNEL_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+pub struct DispersiveDielectric {
+    dispersion: Dispersion,
+}
+
+impl DispersiveDielectric {
+    pub fn new(dispersion: Dispersion) -> Self {
+        Self { dispersion }
+    }
+
+    pub fn bk7() -> Self {
+        Self::new(Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        })
+    }
+
+    pub fn fused_silica() -> Self {
+        Self::new(Dispersion::Sellmeier {
+            b: [0.6961663, 0.4079426, 0.8974794],
+            c: [0.0046791483, 0.0135120631, 97.9340025],
+        })
+    }
+
+    pub fn diamond() -> Self {
+        Self::new(Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        })
+    }
+
+    // The medium inside, as light of `wavelength` sees it.
+    fn interior(&self, wavelength: f64) -> Interior {
+        Interior {
+            id: self as *const Self as usize,
+            priority: 0,
+            refraction_index: self.dispersion.refraction_index(wavelength),
+            absorption: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Material for DispersiveDielectric {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        // In RGB mode a single channel is followed, chosen at random and
+        // weighted by three to keep the estimate unbiased.
+        let (wavelength, attenuation) = match r_in.wavelength() {
+            Some(wavelength) => (wavelength, Color::new(1.0, 1.0, 1.0)),
+            None => {
+                let channel = sampler::random_int(0, 2);
+                let mut attenuation = Color::new(0.0, 0.0, 0.0);
+                attenuation[channel] = 3.0;
+
+                (CHANNEL_WAVELENGTHS[channel], attenuation)
+            }
+        };
+
+        let r_in = r_in.with_wavelength(wavelength);
+        let (beyond, ri) = crossing(r_in, rec, self.interior(wavelength));
+        let Some(ri) = ri else {
+            let ray = beyond.continued(rec.p, r_in.direction());
+
+            return Some(ScatterRecord::SkipPdf {
+                attenuation,
+                ray: Box::new(ray),
+            });
+        };
+
+        let (ray, refracted) = refract_or_reflect(r_in, rec, ri);
+        let ray = if refracted {
+            beyond.continued(ray.origin(), ray.direction())
+        } else {
+            ray
+        };
+
+        Some(ScatterRecord::SkipPdf {
+            attenuation,
+            ray: Box::new(ray),
+        })
+    }
+
+    fn is_dispersive(&self) -> bool {
+        true
+    }
+}
+
+// Exact Fresnel reflectance for unpolarized light at a dielectric boundary,
+// with `refraction_index` the ratio of the incident to the transmitted index.
+pub fn fresnel_dielectric(cos_theta_i: f64, refraction_index: f64) -> f64 {
+    let sin2_theta_t = refraction_index * refraction_index * (1.0 - cos_theta_i * cos_theta_i);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let r_s = (refraction_index * cos_theta_i - cos_theta_t)
+        / (refraction_index * cos_theta_i + cos_theta_t);
+    let r_p = (cos_theta_i - refraction_index * cos_theta_t)
+        / (cos_theta_i + refraction_index * cos_theta_t);
+
+    0.5 * (r_s * r_s + r_p * r_p)
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction
+// `eta + i k`, seen from a vacuum.
+fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i * cos_theta_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_s + r_p)
+}
+
+// A metal described by its complex index of refraction per RGB channel, so
+// its color and the way it brightens towards grazing angles both follow from
+// the Fresnel equations. Smooth unless given a roughness, in which case it
+// is a GGX microfacet surface.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Option<TrowbridgeReitz>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: None,
+        }
+    }
+
+    pub fn gold() -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+        )
+    }
+
+    pub fn silver() -> Self {
+        Self::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+        )
+    }
+
+    pub fn copper() -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+        )
+    }
+
+    pub fn aluminium() -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+        )
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Self {
+        self.with_anisotropic_roughness(roughness, roughness)
+    }
+
+    // Separate roughness along the two tangents of the shading frame.
+    pub fn with_anisotropic_roughness(self, roughness_u: f64, roughness_v: f64) -> Self {
+        let distribution = TrowbridgeReitz::new(
+            TrowbridgeReitz::roughness_to_alpha(roughness_u),
+            TrowbridgeReitz::roughness_to_alpha(roughness_v),
+        );
+
+        Self {
+            distribution: Some(distribution),
+            ..self
+        }
+    }
+
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cos_theta, self.eta.x(), self.k.x()),
+            fresnel_conductor(cos_theta, self.eta.y(), self.k.y()),
+            fresnel_conductor(cos_theta, self.eta.z(), self.k.z()),
+        )
+    }
+
+    // Everything in the microfacet BSDF times cosine but the Fresnel term,
+    // together with the cosine at the microfacet that Fresnel needs.
+    fn microfacet_eval(
+        &self,
+        distribution: &TrowbridgeReitz,
+        r_in: Ray,
+        rec: &HitRecord,
+        scattered: Ray,
+    ) -> Option<(f64, f64)> {
+        let uvw = rec.shading_frame();
+        let wo = uvw.to_local(-vec3::unit_vector(r_in.direction()));
+        let wi = uvw.to_local(vec3::unit_vector(scattered.direction()));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+
+        let wm = vec3::unit_vector(wo + wi);
+        let cos_theta_m = vec3::dot(wo, wm).clamp(0.0, 1.0);
+
+        Some((
+            distribution.d(wm) * distribution.g(wo, wi) / (4.0 * wo.z()),
+            cos_theta_m,
+        ))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let unit_direction = vec3::unit_vector(r_in.direction());
+        let cos_theta = vec3::dot(-unit_direction, rec.normal).clamp(0.0, 1.0);
+        let attenuation = self.fresnel(cos_theta);
+
+        match self.distribution {
+            None => {
+                let ray = r_in.continued(rec.p, vec3::reflect(unit_direction, rec.normal));
+
+                Some(ScatterRecord::SkipPdf {
+                    attenuation,
+                    ray: Box::new(ray),
+                })
+            }
+            Some(distribution) => {
+                let pdf_ptr = Box::new(MicrofacetReflectionPdf::new(
+                    rec.shading_frame(),
+                    -unit_direction,
+                    distribution,
+                ));
+
+                Some(ScatterRecord::Pdf {
+                    attenuation,
+                    pdf_ptr,
+                })
+            }
+        }
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        match self.distribution {
+            None => 0.0,
+            Some(distribution) => MicrofacetReflectionPdf::new(
+                rec.shading_frame(),
+                -vec3::unit_vector(r_in.direction()),
+                distribution,
+            )
+            .value(scattered.direction()),
+        }
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let Some(distribution) = &self.distribution else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+
+        match self.microfacet_eval(distribution, r_in, rec, scattered) {
+            Some((f, cos_theta_m)) => f * self.fresnel(cos_theta_m),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // The channel values are taken as samples at `CHANNEL_WAVELENGTHS` and
+    // interpolated in between.
+    fn spectral_attenuation(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        scattered: Ray,
+        lambda: f64,
+    ) -> Option<f64> {
+        let eta = interpolate_channels(self.eta, lambda);
+        let k = interpolate_channels(self.k, lambda);
+
+        match &self.distribution {
+            None => {
+                let cos_theta =
+                    vec3::dot(-vec3::unit_vector(r_in.direction()), rec.normal).clamp(0.0, 1.0);
+
+                Some(fresnel_conductor(cos_theta, eta, k))
+            }
+            Some(distribution) => {
+                let f = match self.microfacet_eval(distribution, r_in, rec, scattered) {
+                    Some((f, cos_theta_m)) => f * fresnel_conductor(cos_theta_m, eta, k),
+                    None => 0.0,
+                };
+
+                Some(f)
+            }
+        }
+    }
+}
+
+// Piecewise linear through the channels placed at `CHANNEL_WAVELENGTHS`,
+// constant beyond the outer ones.
+fn interpolate_channels(c: Color, lambda: f64) -> f64 {
+    let [red, green, blue] = CHANNEL_WAVELENGTHS;
+
+    if lambda >= red {
+        c.x()
+    } else if lambda >= green {
+        let t = (lambda - green) / (red - green);
+        (1.0 - t) * c.y() + t * c.x()
+    } else if lambda >= blue {
+        let t = (lambda - blue) / (green - blue);
+        (1.0 - t) * c.z() + t * c.y()
+    } else {
+        c.z()
+    }
+}
+
+// Which sides of a surface an emitter lights, front being the side its
+// outward normal points to (for `Quad`, that of `u` cross `v`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmissionSides {
+    Front,
+    Back,
+    Both,
+}
+
+impl EmissionSides {
+    pub fn emits(self, front_face: bool) -> bool {
+        match self {
+            EmissionSides::Front => front_face,
+            EmissionSides::Back => !front_face,
+            EmissionSides::Both => true,
+        }
+    }
+
+    // The sides lit by either of two emitters.
+    pub fn union(self, other: EmissionSides) -> EmissionSides {
+        if self == other {
+            self
+        } else {
+            EmissionSides::Both
+        }
+    }
+}
+
+// Lumens per watt at the peak of the eye's sensitivity, relating radiance in
+// scene units to nits.
+pub const LUMINOUS_EFFICACY: f64 = 683.0;
+
+// How bright a `DiffuseLight` is, multiplying its texture.
+enum EmissionStrength {
+    Scale(f64),
+    // Total watts, split between the sides that emit. Spread over a unit
+    // area until the light learns the area of its surface.
+    Power(f64),
+}
+
+// An area light whose radiance is its texture times a strength, so color
+// and intensity can be given apart: a color (or an `ImageTexture` screen)
+// with luminance around one, then `with_radiance` in nits or `with_power` in
+// watts.
+pub struct DiffuseLight {
+    tex: Box<dyn Texture>,
+    strength: EmissionStrength,
+    sides: EmissionSides,
+    // Temperature and unscaled luminance, for the exact spectrum in spectral
+    // mode.
+    blackbody: Option<(f64, f64)>,
+}
+
+impl DiffuseLight {
+    pub fn new(tex: Box<dyn Texture>) -> Self {
+        Self {
+            tex,
+            strength: EmissionStrength::Scale(1.0),
+            sides: EmissionSides::Front,
+            blackbody: None,
+        }
+    }
+
+    pub fn from_color(albedo: Color) -> Self {
+        Self::new(Box::new(SolidColor::new(albedo)))
+    }
+
+    // The color of a blackbody at `kelvin`, at unit luminance.
+    pub fn from_blackbody(kelvin: f64) -> Self {
+        Self {
+            blackbody: Some((kelvin, spectrum::blackbody_luminance(kelvin))),
+            ..Self::from_color(spectrum::blackbody_rgb(kelvin))
+        }
+    }
+
+    // Luminance in nits (cd/m^2) where the texture is white.
+    pub fn with_radiance(self, nits: f64) -> Self {
+        Self {
+            strength: EmissionStrength::Scale(nits / LUMINOUS_EFFICACY),
+            ..self
+        }
+    }
+
+    // Total power in watts for a white texture, over the whole of the `Quad`,
+    // `Sphere`, `Triangle` or OBJ mesh the light is put on.
+    pub fn with_power(self, watts: f64) -> Self {
+        Self {
+            strength: EmissionStrength::Power(watts),
+            ..self
+        }
+    }
+
+    pub fn with_sides(self, sides: EmissionSides) -> Self {
+        Self { sides, ..self }
+    }
+
+    fn scale(&self) -> f64 {
+        match self.strength {
+            EmissionStrength::Scale(scale) => scale,
+            EmissionStrength::Power(watts) => self.radiance_for(watts, 1.0),
+        }
+    }
+
+    fn radiance_for(&self, watts: f64, area: f64) -> f64 {
+        let sides = if self.sides == EmissionSides::Both {
+            2.0
+        } else {
+            1.0
+        };
+
+        watts / (sides * f64::consts::PI * area)
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, _r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        if !self.sides.emits(rec.front_face) {
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            self.scale() * self.tex.value(u, v, p)
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn spectral_emission(&self, _r_in: Ray, rec: &HitRecord, lambda: f64) -> Option<f64> {
+        let (kelvin, luminance) = self.blackbody?;
+        if !self.sides.emits(rec.front_face) {
+            return Some(0.0);
+        }
+
+        Some(self.scale() * spectrum::blackbody(lambda, kelvin) / luminance)
+    }
+
+    fn emission_sides(&self) -> EmissionSides {
+        self.sides
+    }
+
+    fn set_emitter_area(&mut self, area: f64) {
+        if let EmissionStrength::Power(watts) = self.strength {
+            self.strength = EmissionStrength::Scale(self.radiance_for(watts, area));
+        }
+    }
+}
+
+pub struct Isotropic {
+    tex: Box<dyn Texture>,
+    emit: bool,
+}
+
+impl Isotropic {
+    pub fn from_color(albedo: Color) -> Self {
+        Self {
+            tex: Box::new(SolidColor::new(albedo)),
+            emit: false,
+        }
+    }
+
+    pub fn from_texture(tex: Box<dyn Texture>) -> Self {
+        Self { tex, emit: false }
+    }
+
+    pub fn from_color_emit(albedo: Color) -> Self {
+        Self {
+            tex: Box::new(SolidColor::new(albedo)),
+            emit: true,
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let attenuation = self.tex.value(rec.u, rec.v, rec.p);
+        let pdf_ptr = Box::new(SpherePdf {});
+
+        Some(ScatterRecord::Pdf {
+            attenuation,
+            pdf_ptr,
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
+        1.0 / (4.0 * f64::consts::PI)
+    }
+
+    fn eval(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.tex.value(rec.u, rec.v, rec.p) * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+
+    fn emitted(&self, _r_in: Ray, _rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        if self.emit {
+            self.tex.value(u, v, p)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Refractive indices at the helium d line, 587.6nm.
+    #[test]
+    fn sellmeier_presets_match_catalog_indices() {
+        let d_line = 587.56;
+        let cases = [
+            (DispersiveDielectric::bk7(), 1.5168),
+            (DispersiveDielectric::fused_silica(), 1.4585),
+            (DispersiveDielectric::diamond(), 2.4175),
+        ];
+
+        for (material, expected) in cases {
+            let n = material.dispersion.refraction_index(d_line);
+            assert!((n - expected).abs() < 5e-4, "{n} != {expected}");
+        }
+    }
+
+    #[test]
+    fn glass_disperses_blue_more_than_red() {
+        let bk7 = DispersiveDielectric::bk7().dispersion;
+
+        assert!(bk7.refraction_index(450.0) > bk7.refraction_index(550.0));
+        assert!(bk7.refraction_index(550.0) > bk7.refraction_index(650.0));
+    }
+
+    #[test]
+    fn fresnel_dielectric_known_values() {
+        // Glass seen from air: 4% at normal incidence, everything at grazing.
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.0 / 1.5) - 1.0).abs() < 1e-12);
+
+        // At Brewster's angle only the s-polarized half is reflected.
+        let cos_brewster = (1.5f64.atan()).cos();
+        assert!((fresnel_dielectric(cos_brewster, 1.0 / 1.5) - 0.0740).abs() < 1e-4);
+
+        // Past the critical angle from inside the glass.
+        assert_eq!(fresnel_dielectric(0.5, 1.5), 1.0);
+    }
+
+    #[test]
+    fn fresnel_conductor_known_values() {
+        // Gold's red channel at normal incidence.
+        let (eta, k) = (0.143, 3.983);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-12);
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-12);
+
+        // Without absorption a conductor is a dielectric.
+        for cos_theta in [0.1, 0.3, 0.5, 0.7, 0.9, 1.0] {
+            let conductor = fresnel_conductor(cos_theta, 1.5, 0.0);
+            let dielectric = fresnel_dielectric(cos_theta, 1.0 / 1.5);
+            assert!((conductor - dielectric).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cauchy_matches_its_formula() {
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+
+        assert!((cauchy.refraction_index(500.0) - (1.5 + 0.004 / 0.25)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mix_forwards_emission_and_volume() {
+        let half = || Box::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
+        let white = Color::new(1.0, 1.0, 1.0);
+        let front = || Box::new(DiffuseLight::from_color(white));
+        let back = || Box::new(DiffuseLight::from_color(white).with_sides(EmissionSides::Back));
+        let surface = || Box::new(Lambertian::from_color(white));
+
+        let lit = MixMaterial::new(surface(), back(), half());
+        assert!(lit.is_emissive());
+        assert_eq!(lit.emission_sides(), EmissionSides::Back);
+        assert!(!lit.is_volumetric());
+
+        let both = MixMaterial::new(front(), back(), half());
+        assert_eq!(both.emission_sides(), EmissionSides::Both);
+
+        let hazy = MixMaterial::new(surface(), Box::new(Isotropic::from_color(white)), half());
+        assert!(hazy.is_volumetric());
+        assert!(!hazy.is_emissive());
+    }
+
+    #[test]
+    fn power_is_spread_over_the_shape() {
+        use crate::hittable::Hittable;
+        use crate::obj_loader::load_obj;
+        use crate::quad::Quad;
+        use crate::sphere::Sphere;
+        use crate::triangle::Triangle;
+
+        let light = || DiffuseLight::from_color(Color::new(1.0, 1.0, 1.0)).with_power(100.0);
+        let quad = Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Box::new(light()),
+        );
+        let panel = Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Box::new(light().with_sides(EmissionSides::Both)),
+        );
+        let sphere = Sphere::new_static(Point3::new(0.0, 0.0, 0.0), 5.0, Box::new(light()));
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Box::new(light()),
+        );
+
+        // A mesh is one light, its triangles sharing the power by area.
+        let obj = std::env::temp_dir().join(format!("rtiow-power-{}.obj", std::process::id()));
+        std::fs::write(
+            &obj,
+            "v 0 0 0\nv 2 0 0\nv 2 3 0\nv 0 3 0\nf 1 2 3\nf 1 3 4\n",
+        )
+        .unwrap();
+        let mesh = load_obj(obj.to_str().unwrap(), 1.0, Box::new(light()));
+        std::fs::remove_file(&obj).unwrap();
+
+        for power in [
+            quad.power(),
+            panel.power(),
+            sphere.power(),
+            triangle.power(),
+            mesh.power(),
+        ] {
+            assert!((power - 100.0).abs() < 1e-6, "power {power}");
+        }
+    }
+
+    #[test]
+    fn anisotropic_conductor_follows_the_tangent() {
+        let along_x = Conductor::aluminium().with_anisotropic_roughness(0.1, 0.5);
+        let along_y = Conductor::aluminium().with_anisotropic_roughness(0.5, 0.1);
+        fn rec(mat: &dyn Material, tangent: Vec3) -> HitRecord<'_> {
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            HitRecord::new(Point3::new(0.0, 0.0, 0.0), normal, mat, 1.0, 0.0, 0.0, true)
+                .with_tangents(tangent, vec3::cross(normal, tangent))
+        }
+
+        // Turning the tangent a quarter turn swaps the two roughnesses.
+        let x = rec(&along_x, Vec3::new(1.0, 0.0, 0.0));
+        let y = rec(&along_y, Vec3::new(0.0, 1.0, 0.0));
+        let r_in = Ray::new(Point3::new(-1.0, -0.5, 1.0), Vec3::new(1.0, 0.5, -1.0), 0.0);
+        for direction in [
+            Vec3::new(1.0, 0.2, 1.0),
+            Vec3::new(0.3, 0.9, 0.8),
+            Vec3::new(-0.4, 0.6, 0.5),
+        ] {
+            let scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), direction, 0.0);
+            let fx = along_x.eval(r_in, &x, scattered);
+            let fy = along_y.eval(r_in, &y, scattered);
+            assert!((fx - fy).length() < 1e-9, "{fx:?} != {fy:?}");
+
+            let pdf_x = along_x.scattering_pdf(r_in, &x, scattered);
+            let pdf_y = along_y.scattering_pdf(r_in, &y, scattered);
+            assert!((pdf_x - pdf_y).abs() < 1e-9);
+        }
+
+        // While the same surface brushed the other way looks different.
+        let other = rec(&along_x, Vec3::new(0.0, 1.0, 0.0));
+        let scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.2, 1.0), 0.0);
+        let f = along_x.eval(r_in, &x, scattered) - along_x.eval(r_in, &other, scattered);
+        assert!(f.length() > 1e-3);
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let albedo = Color::new(0.7, 0.45, 0.3);
+        let lambertian = Lambertian::from_color(albedo);
+        let oren_nayar = OrenNayar::from_color(albedo, 0.0);
+
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let p = Point3::new(0.0, 0.0, 0.0);
+        let rec = HitRecord::new(p, normal, &lambertian, 1.0, 0.0, 0.0, true);
+        for incoming in [
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.3, -0.5),
+            Vec3::new(-0.2, 0.9, -0.1),
+        ] {
+            let r_in = Ray::new(p - incoming, incoming, 0.0);
+            for direction in [
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.5, -0.4, 0.7),
+                Vec3::new(-0.9, 0.1, 0.2),
+                Vec3::new(0.3, 0.3, -0.8),
+            ] {
+                let scattered = Ray::new(p, direction, 0.0);
+                let expected = lambertian.eval(r_in, &rec, scattered);
+                let f = oren_nayar.eval(r_in, &rec, scattered);
+                assert!((f - expected).length() < 1e-12, "{f:?} != {expected:?}");
+            }
         }
     }
 }
@@ -0,0 +1,163 @@
+use core::f64;
+
+use crate::sampler;
+use crate::vec3::{self, Vec3};
+
+// The Trowbridge-Reitz (GGX) microfacet distribution with Smith's
+// height-correlated masking-shadowing. Directions are in the local shading
+// frame, with the macro surface normal along +z; the roughness along x and y
+// may differ for anisotropic surfaces.
+
+#[derive(Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        // Below this the distribution is too sharp to sample or evaluate in
+        // floating point.
+        Self {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    // Maps a perceptually linear roughness in [0, 1] to alpha.
+    pub fn roughness_to_alpha(roughness: f64) -> f64 {
+        roughness * roughness
+    }
+
+    // Density of microfacet normals `wm`, per unit projected area.
+    pub fn d(&self, wm: Vec3) -> f64 {
+        if wm.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let t = x * x + y * y + wm.z() * wm.z();
+
+        1.0 / (f64::consts::PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let z2 = w.z() * w.z();
+        if z2 == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let ax = self.alpha_x * w.x();
+        let ay = self.alpha_y * w.y();
+        let tan2_alpha2 = (ax * ax + ay * ay) / z2;
+
+        0.5 * (-1.0 + (1.0 + tan2_alpha2).sqrt())
+    }
+
+    // Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Fraction of microfacets visible from both `wo` and `wi`.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals `sample_wm` returns: those visible from `w`.
+    pub fn visible_d(&self, w: Vec3, wm: Vec3) -> f64 {
+        let cos_theta = w.z().abs();
+        if cos_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.g1(w) * vec3::dot(w, wm).max(0.0) * self.d(wm) / cos_theta
+    }
+
+    // The microfacet normal that scatters `wo` into `wi`, given the ratio
+    // `eta` of the index below the surface to the one above; `None` for
+    // degenerate configurations or a normal seen from behind by either.
+    pub fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+        let eta = if wi.z() > 0.0 { 1.0 } else { eta };
+        let wm = wi * eta + wo;
+        if wi.z() == 0.0 || wo.z() == 0.0 || wm.length_squared() == 0.0 {
+            return None;
+        }
+
+        let wm = vec3::unit_vector(wm);
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        if vec3::dot(wm, wi) * wi.z() < 0.0 || vec3::dot(wm, wo) * wo.z() < 0.0 {
+            return None;
+        }
+
+        Some(wm)
+    }
+
+    // Samples a microfacet normal visible from `w` (Heitz 2018): stretch the
+    // view direction to the unit roughness configuration, sample the
+    // projected hemisphere there and unstretch.
+    pub fn sample_wm(&self, w: Vec3) -> Vec3 {
+        let w = if w.z() < 0.0 { -w } else { w };
+        let wh = vec3::unit_vector(Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()));
+
+        let len_squared = wh.x() * wh.x() + wh.y() * wh.y();
+        let t1 = if len_squared > 0.0 {
+            Vec3::new(-wh.y(), wh.x(), 0.0) / len_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vec3::cross(wh, t1);
+
+        let r = sampler::random_double().sqrt();
+        let phi = 2.0 * f64::consts::PI * sampler::random_double();
+        let p1 = r * phi.cos();
+        let p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + wh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        let nh = p1 * t1 + p2 * t2 + p3 * wh;
+
+        vec3::unit_vector(Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Integrates `D(wm) cos(theta)` over the hemisphere on a grid in
+    // cos(theta) and phi, where the solid angle element is uniform.
+    fn projected_area(distribution: &TrowbridgeReitz) -> f64 {
+        let (n_cos, n_phi) = (4000, 256);
+        let mut total = 0.0;
+
+        for i in 0..n_cos {
+            let cos_theta = (i as f64 + 0.5) / n_cos as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for j in 0..n_phi {
+                let phi = 2.0 * f64::consts::PI * (j as f64 + 0.5) / n_phi as f64;
+                let wm = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                total += distribution.d(wm) * cos_theta;
+            }
+        }
+
+        total * 2.0 * f64::consts::PI / (n_cos * n_phi) as f64
+    }
+
+    #[test]
+    fn d_is_normalized() {
+        for (alpha_x, alpha_y) in [(0.3, 0.3), (0.6, 0.6), (1.0, 1.0), (0.5, 0.2)] {
+            let area = projected_area(&TrowbridgeReitz::new(alpha_x, alpha_y));
+            assert!(
+                (area - 1.0).abs() < 1e-3,
+                "alpha ({alpha_x}, {alpha_y}) projects to {area}"
+            );
+        }
+    }
+}
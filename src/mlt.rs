@@ -0,0 +1,135 @@
+use std::sync::Mutex;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::camera::{self, Camera};
+use crate::color::{self, Color};
+use crate::hittable::Hittable;
+use crate::sampler::{self, PrimarySamples};
+
+// Primary sample space Metropolis light transport (Kelemen et al. 2002).
+// A path is identified with the vector of random numbers that `ray_color`
+// consumes while tracing it, so mutating those numbers explores path space
+// without the integrator knowing anything about materials or lights. A
+// bootstrap pass estimates the image's mean luminance, which fixes the
+// overall brightness that the chains, sampling in proportion to luminance,
+// cannot see.
+
+const SIGMA: f64 = 0.01;
+const LARGE_STEP_PROBABILITY: f64 = 0.3;
+
+pub fn render(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    mutations_per_pixel: usize,
+    bootstrap_samples: usize,
+    chains: usize,
+) -> Vec<Color> {
+    let width = cam.image_width();
+    let height = cam.image_height();
+    let black = Color::new(0.0, 0.0, 0.0);
+
+    let bootstrap_weights: Vec<f64> = (0..bootstrap_samples)
+        .into_par_iter()
+        .map(|seed| {
+            let samples = PrimarySamples::new(seed as u64, SIGMA, LARGE_STEP_PROBABILITY);
+            let ((_, l), _) =
+                sampler::with_primary_samples(samples, || path_sample(cam, world, lights));
+
+            color::luminance(l)
+        })
+        .collect();
+
+    let total_weight: f64 = bootstrap_weights.iter().sum();
+    if total_weight <= 0.0 {
+        return vec![black; (width * height) as usize];
+    }
+
+    let b = total_weight / bootstrap_samples as f64;
+    let cdf: Vec<f64> = bootstrap_weights
+        .iter()
+        .scan(0.0, |sum, w| {
+            *sum += w / total_weight;
+            Some(*sum)
+        })
+        .collect();
+
+    let total_mutations = mutations_per_pixel * (width * height) as usize;
+    let splats: Vec<Mutex<Color>> = (0..width * height).map(|_| Mutex::new(black)).collect();
+    let splat =
+        |(i, j): (i32, i32), c: Color| *splats[(j * width + i) as usize].lock().unwrap() += c;
+
+    (0..chains).into_par_iter().for_each(|chain| {
+        let chain_mutations = ((chain + 1) * total_mutations / chains).min(total_mutations)
+            - chain * total_mutations / chains;
+        let mut rng = StdRng::seed_from_u64((bootstrap_samples + chain) as u64);
+
+        // Restart a bootstrap path chosen in proportion to its luminance;
+        // reusing its seed reproduces the same primary samples.
+        let u: f64 = rng.random();
+        let seed = cdf.partition_point(|&c| c < u).min(bootstrap_samples - 1);
+        let samples = PrimarySamples::new(seed as u64, SIGMA, LARGE_STEP_PROBABILITY);
+        let ((mut pixel_current, mut l_current), mut samples) =
+            sampler::with_primary_samples(samples, || path_sample(cam, world, lights));
+        let mut y_current = color::luminance(l_current);
+
+        for _ in 0..chain_mutations {
+            samples.start_iteration();
+            let ((pixel_proposed, l_proposed), proposed) =
+                sampler::with_primary_samples(samples, || path_sample(cam, world, lights));
+            samples = proposed;
+            let y_proposed = color::luminance(l_proposed);
+
+            let accept = if y_current > 0.0 {
+                (y_proposed / y_current).min(1.0)
+            } else {
+                1.0
+            };
+
+            // Expected values: both states are splatted, weighted by their
+            // acceptance probability. A black proposal adds nothing, even
+            // when it is accepted for certain from a black current state.
+            if accept > 0.0 && y_proposed > 0.0 {
+                splat(pixel_proposed, l_proposed * accept / y_proposed);
+            }
+            if y_current > 0.0 {
+                splat(pixel_current, l_current * (1.0 - accept) / y_current);
+            }
+
+            if rng.random::<f64>() < accept {
+                pixel_current = pixel_proposed;
+                l_current = l_proposed;
+                y_current = y_proposed;
+                samples.accept();
+            } else {
+                samples.reject();
+            }
+        }
+
+        eprintln!("Chain {} of {} done", chain + 1, chains);
+    });
+
+    let scale = b / mutations_per_pixel as f64;
+    splats
+        .into_iter()
+        .map(|splat| scale * splat.into_inner().unwrap())
+        .collect()
+}
+
+// The path tracer as a function of primary sample space: the first two
+// numbers pick the film position, the rest drive `ray_color`.
+fn path_sample(cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable) -> ((i32, i32), Color) {
+    let x = sampler::random_double() * cam.image_width() as f64;
+    let y = sampler::random_double() * cam.image_height() as f64;
+    let r = cam.get_ray_at(x, y);
+    let l = camera::ray_color(cam, r, cam.max_depth(), world, lights);
+    let pixel = (
+        (x as i32).min(cam.image_width() - 1),
+        (y as i32).min(cam.image_height() - 1),
+    );
+
+    (pixel, l)
+}
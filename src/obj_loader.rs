@@ -2,11 +2,11 @@ use crate::bvh::BVHNode;
 use crate::hittable_list::HittableList;
 use crate::material::Material;
 use crate::triangle::Triangle;
-use crate::vec3::Point3;
+use crate::vec3::{self, Point3};
 use std::sync::Arc;
 use tobj;
 
-pub fn load_obj(file_name: &str, scale: f64, mat: Arc<dyn Material>) -> BVHNode {
+pub fn load_obj(file_name: &str, scale: f64, mut mat: Box<dyn Material>) -> BVHNode {
     let options = tobj::LoadOptions {
         triangulate: true,
         single_index: true,
@@ -14,7 +14,7 @@ pub fn load_obj(file_name: &str, scale: f64, mat: Arc<dyn Material>) -> BVHNode
     };
     match tobj::load_obj(file_name, &options) {
         Ok((models, _)) => {
-            let mut meshes = HittableList::new();
+            let mut triangles = Vec::new();
 
             eprintln!("Num models: {}", models.len());
             for m in models {
@@ -34,15 +34,44 @@ pub fn load_obj(file_name: &str, scale: f64, mat: Arc<dyn Material>) -> BVHNode
                     })
                     .collect();
 
+                let texcoords: Vec<(f64, f64)> = mesh
+                    .texcoords
+                    .chunks(2)
+                    .map(|i| (i[0] as f64, i[1] as f64))
+                    .collect();
+
                 for tri in mesh.indices.chunks(3) {
-                    let v0 = positions[tri[0] as usize];
-                    let v1 = positions[tri[1] as usize];
-                    let v2 = positions[tri[2] as usize];
+                    let vertex = |k: usize| positions[tri[k] as usize];
+                    let uvs = if texcoords.is_empty() {
+                        None
+                    } else {
+                        let uv = |k: usize| texcoords[tri[k] as usize];
+                        Some((uv(0), uv(2), uv(1)))
+                    };
 
-                    meshes.add(Box::new(Triangle::new(v0, v2, v1, mat.clone())));
+                    triangles.push((vertex(0), vertex(2), vertex(1), uvs));
                 }
             }
 
+            // The mesh is one emitter as far as a total power goes.
+            let area: f64 = triangles
+                .iter()
+                .map(|(v0, v1, v2, _)| 0.5 * vec3::cross(*v1 - *v0, *v2 - *v0).length())
+                .sum();
+            mat.set_emitter_area(area);
+            let mat: Arc<dyn Material> = Arc::from(mat);
+
+            let mut meshes = HittableList::new();
+            for (v0, v1, v2, uvs) in triangles {
+                let triangle = Triangle::shared(v0, v1, v2, mat.clone());
+                let triangle = match uvs {
+                    Some((uv0, uv1, uv2)) => triangle.with_uvs(uv0, uv1, uv2),
+                    None => triangle,
+                };
+
+                meshes.add(Box::new(triangle));
+            }
+
             return BVHNode::from_list(meshes);
         }
         Err(e) => {
@@ -1,5 +1,6 @@
 use crate::vec3::{self, Vec3};
 
+#[derive(Clone, Copy)]
 pub struct Onb {
     u: Vec3,
     v: Vec3,
@@ -20,6 +21,22 @@ impl Onb {
         Self { u, v, w }
     }
 
+    // A basis around `n` whose first axis follows `t` as closely as it can,
+    // so anisotropic surfaces line up with their parameterization. Falls back
+    // to `new` where `t` is zero or along `n`.
+    pub fn from_tangent(n: Vec3, t: Vec3) -> Self {
+        let w = vec3::unit_vector(n);
+        let t = t - vec3::dot(t, w) * w;
+        if t.length_squared() == 0.0 || t.near_zero() {
+            return Self::new(n);
+        }
+
+        let u = vec3::unit_vector(t);
+        let v = vec3::cross(w, u);
+
+        Self { u, v, w }
+    }
+
     pub fn u(&self) -> Vec3 {
         self.u
     }
@@ -35,4 +52,13 @@ impl Onb {
     pub fn transform(&self, v: Vec3) -> Vec3 {
         (v.x() * self.u()) + (v.y() * self.v()) + (v.z() * self.w())
     }
+
+    // The inverse of `transform`: world space to coordinates in this basis.
+    pub fn to_local(self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            vec3::dot(v, self.u()),
+            vec3::dot(v, self.v()),
+            vec3::dot(v, self.w()),
+        )
+    }
 }
@@ -1,13 +1,17 @@
-use rand::Rng;
-
 use crate::hittable::Hittable;
+use crate::material::{self, EmissionSides};
+use crate::microfacet::TrowbridgeReitz;
 use crate::onb::Onb;
+use crate::sampler;
 use crate::vec3::{self, Point3, Vec3};
 use core::f64;
 
 pub trait Pdf {
     fn value(&self, direction: Vec3) -> f64;
-    fn generate(&self) -> Vec3;
+
+    // A direction drawn from the density, or `None` if the draw landed
+    // somewhere nothing can be scattered into, such as below the surface.
+    fn generate(&self) -> Option<Vec3>;
 }
 
 pub struct SpherePdf {}
@@ -17,8 +21,8 @@ impl Pdf for SpherePdf {
         1.0 / (4.0 * f64::consts::PI)
     }
 
-    fn generate(&self) -> Vec3 {
-        vec3::random_unit_vector()
+    fn generate(&self) -> Option<Vec3> {
+        Some(vec3::random_unit_vector())
     }
 }
 
@@ -39,8 +43,247 @@ impl Pdf for CosinePdf {
         (cosine_theta / f64::consts::PI).max(0.0)
     }
 
-    fn generate(&self) -> Vec3 {
-        self.uvw.transform(vec3::random_cosine_direction())
+    fn generate(&self) -> Option<Vec3> {
+        Some(self.uvw.transform(vec3::random_cosine_direction()))
+    }
+}
+
+// Emission directions of an area light with outward normal `normal`,
+// cosine-weighted over the hemispheres it lights.
+pub struct EmissionPdf {
+    uvw: Onb,
+    sides: EmissionSides,
+}
+
+impl EmissionPdf {
+    pub fn new(normal: Vec3, sides: EmissionSides) -> Self {
+        Self {
+            uvw: Onb::new(normal),
+            sides,
+        }
+    }
+}
+
+impl Pdf for EmissionPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine_theta = vec3::dot(vec3::unit_vector(direction), self.uvw.w());
+
+        match self.sides {
+            EmissionSides::Front => (cosine_theta / f64::consts::PI).max(0.0),
+            EmissionSides::Back => (-cosine_theta / f64::consts::PI).max(0.0),
+            EmissionSides::Both => cosine_theta.abs() / (2.0 * f64::consts::PI),
+        }
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        let direction = self.uvw.transform(vec3::random_cosine_direction());
+        let back = match self.sides {
+            EmissionSides::Front => false,
+            EmissionSides::Back => true,
+            EmissionSides::Both => sampler::random_double() < 0.5,
+        };
+
+        if back {
+            Some(direction - 2.0 * vec3::dot(direction, self.uvw.w()) * self.uvw.w())
+        } else {
+            Some(direction)
+        }
+    }
+}
+
+// Mirror reflection about microfacet normals sampled from those visible from
+// `wo`, the unit direction back along the incoming ray.
+pub struct MicrofacetReflectionPdf {
+    uvw: Onb,
+    wo: Vec3,
+    distribution: TrowbridgeReitz,
+}
+
+impl MicrofacetReflectionPdf {
+    pub fn new(uvw: Onb, wo: Vec3, distribution: TrowbridgeReitz) -> Self {
+        let wo = uvw.to_local(wo);
+
+        Self {
+            uvw,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for MicrofacetReflectionPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let wi = self.uvw.to_local(vec3::unit_vector(direction));
+        if wi.z() <= 0.0 || self.wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = vec3::unit_vector(self.wo + wi);
+
+        self.distribution.visible_d(self.wo, wm) / (4.0 * vec3::dot(self.wo, wm))
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        let wm = self.distribution.sample_wm(self.wo);
+        let wi = vec3::reflect(-self.wo, wm);
+
+        // Microfacets seen at grazing angles can reflect below the surface.
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        Some(self.uvw.transform(wi))
+    }
+}
+
+// Reflection or refraction through microfacet normals visible from `wo`,
+// each chosen in proportion to its Fresnel weight (Walter et al. 2007).
+// `eta` is the index below the surface over the one above.
+pub struct MicrofacetTransmissionPdf {
+    uvw: Onb,
+    wo: Vec3,
+    eta: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl MicrofacetTransmissionPdf {
+    pub fn new(uvw: Onb, wo: Vec3, eta: f64, distribution: TrowbridgeReitz) -> Self {
+        let wo = uvw.to_local(wo);
+
+        Self {
+            uvw,
+            wo,
+            eta,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for MicrofacetTransmissionPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let wo = self.wo;
+        let wi = self.uvw.to_local(vec3::unit_vector(direction));
+        let Some(wm) = TrowbridgeReitz::half_vector(wo, wi, self.eta) else {
+            return 0.0;
+        };
+
+        let r = material::fresnel_dielectric(vec3::dot(wo, wm), 1.0 / self.eta);
+
+        if wi.z() > 0.0 {
+            r * self.distribution.visible_d(wo, wm) / (4.0 * vec3::dot(wo, wm).abs())
+        } else {
+            let denom = vec3::dot(wi, wm) + vec3::dot(wo, wm) / self.eta;
+            let dwm_dwi = vec3::dot(wi, wm).abs() / (denom * denom);
+
+            (1.0 - r) * self.distribution.visible_d(wo, wm) * dwm_dwi
+        }
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        let wo = self.wo;
+        let wm = self.distribution.sample_wm(wo);
+        let cos_theta_o = vec3::dot(wo, wm);
+        let r = material::fresnel_dielectric(cos_theta_o, 1.0 / self.eta);
+
+        let reflected = sampler::random_double() < r;
+        let wi = if reflected {
+            vec3::reflect(-wo, wm)
+        } else {
+            vec3::refract(-wo, wm, 1.0 / self.eta)
+        };
+
+        // A direction on the wrong side for the chosen lobe can't be
+        // scattered into.
+        if (wi.z() > 0.0) != reflected || wi.z() == 0.0 {
+            return None;
+        }
+
+        Some(self.uvw.transform(wi))
+    }
+}
+
+// Picks one of several pdfs, each with the probability it is paired with;
+// the probabilities are normalized to sum to one.
+pub struct WeightedPdf {
+    pdfs: Vec<(f64, Box<dyn Pdf>)>,
+}
+
+impl WeightedPdf {
+    pub fn new(pdfs: Vec<(f64, Box<dyn Pdf>)>) -> Self {
+        let total: f64 = pdfs.iter().map(|(weight, _)| weight).sum();
+        let pdfs = pdfs
+            .into_iter()
+            .filter(|(weight, _)| *weight > 0.0)
+            .map(|(weight, pdf)| (weight / total, pdf))
+            .collect();
+
+        Self { pdfs }
+    }
+}
+
+impl Pdf for WeightedPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.pdfs
+            .iter()
+            .map(|(weight, pdf)| weight * pdf.value(direction))
+            .sum()
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        let mut u = sampler::random_double();
+
+        for (weight, pdf) in &self.pdfs {
+            if u < *weight {
+                return pdf.generate();
+            }
+            u -= weight;
+        }
+
+        self.pdfs.last().unwrap().1.generate()
+    }
+}
+
+// Directions leaving a smooth dielectric coat of relative index `eta`, for a
+// pdf over the directions inside it: samples refract out through the top
+// interface, and the density picks up the change in solid angle.
+pub struct RefractedPdf {
+    inner: Box<dyn Pdf>,
+    normal: Vec3,
+    eta: f64,
+}
+
+impl RefractedPdf {
+    pub fn new(inner: Box<dyn Pdf>, normal: Vec3, eta: f64) -> Self {
+        Self { inner, normal, eta }
+    }
+}
+
+impl Pdf for RefractedPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let w = vec3::unit_vector(direction);
+        let cos_theta = vec3::dot(w, self.normal);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let inside = -vec3::refract(-w, self.normal, 1.0 / self.eta);
+        let cos_theta_inside = vec3::dot(inside, self.normal);
+
+        self.inner.value(inside) * cos_theta / (self.eta * self.eta * cos_theta_inside)
+    }
+
+    fn generate(&self) -> Option<Vec3> {
+        let inside = vec3::unit_vector(self.inner.generate()?);
+        let cos_theta_inside = vec3::dot(inside, self.normal);
+
+        // Directions heading down, or trapped by total internal reflection,
+        // never leave.
+        let sin2_theta = self.eta * self.eta * (1.0 - cos_theta_inside * cos_theta_inside);
+        if cos_theta_inside <= 0.0 || sin2_theta >= 1.0 {
+            return None;
+        }
+
+        Some(vec3::refract(inside, -self.normal, self.eta))
     }
 }
 
@@ -60,18 +303,18 @@ impl<'a> Pdf for HittablePdf<'a> {
         self.objects.pdf_value(self.origin, direction)
     }
 
-    fn generate(&self) -> Vec3 {
-        self.objects.random(self.origin)
+    fn generate(&self) -> Option<Vec3> {
+        Some(self.objects.random(self.origin))
     }
 }
 
 pub struct MixturePdf<'a> {
     p0: &'a dyn Pdf,
-    p1: Box<dyn Pdf>,
+    p1: Box<dyn Pdf + 'a>,
 }
 
 impl<'a> MixturePdf<'a> {
-    pub fn new(p0: &'a dyn Pdf, p1: Box<dyn Pdf>) -> Self {
+    pub fn new(p0: &'a dyn Pdf, p1: Box<dyn Pdf + 'a>) -> Self {
         Self { p0, p1 }
     }
 }
@@ -81,8 +324,8 @@ impl<'a> Pdf for MixturePdf<'a> {
         0.5 * self.p0.value(direction) + 0.5 * self.p1.value(direction)
     }
 
-    fn generate(&self) -> Vec3 {
-        if rand::rng().random::<f64>() < 0.5 {
+    fn generate(&self) -> Option<Vec3> {
+        if sampler::random_double() < 0.5 {
             self.p0.generate()
         } else {
             self.p1.generate()
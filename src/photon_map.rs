@@ -0,0 +1,682 @@
+use core::f64;
+
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light;
+use crate::material::ScatterRecord;
+use crate::pdf::{EmissionPdf, Pdf};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
+
+// Photon mapping after Jensen: photons are shot from the lights and stored
+// where they land on non-specular surfaces, then radiance is estimated from
+// the density of nearby photons. `render` builds a global and a caustic map
+// once and uses final gathering; `render_progressive` is stochastic
+// progressive photon mapping (SPPM), which shrinks a per-pixel gather radius
+// over iterations so the estimate converges.
+
+#[derive(Clone, Copy)]
+pub struct Photon {
+    p: Point3,
+    n: Vec3,  // surface normal facing the side the photon arrived from
+    wi: Vec3, // unit direction back towards where the photon came from
+    power: Color,
+    bounces: i32,
+    caustic: bool, // only specular bounces between the light and here
+}
+
+// A kd-tree over photon positions, stored implicitly: the median of every
+// slice is its node and the halves on either side are its children.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build(&mut photons, &mut axes);
+
+        Self { photons, axes }
+    }
+
+    // Up to `k` photons closest to `p` within `max_dist_squared`, together
+    // with the squared radius of the disc that contains them.
+    pub fn nearest(&self, p: Point3, k: usize, max_dist_squared: f64) -> (Vec<&Photon>, f64) {
+        let mut found: Vec<(f64, usize)> = Vec::with_capacity(k + 1);
+        let mut radius_squared = max_dist_squared;
+
+        self.search(
+            0,
+            self.photons.len(),
+            p,
+            &mut radius_squared,
+            &mut |d2, i, r2| {
+                found.push((d2, i));
+                if found.len() > k {
+                    let farthest = (0..found.len())
+                        .max_by(|&a, &b| found[a].0.total_cmp(&found[b].0))
+                        .unwrap();
+                    found.swap_remove(farthest);
+                }
+                if found.len() == k {
+                    *r2 = found.iter().fold(0.0, |m, &(d2, _)| f64::max(m, d2));
+                }
+            },
+        );
+
+        let photons = found.iter().map(|&(_, i)| &self.photons[i]).collect();
+
+        (photons, radius_squared)
+    }
+
+    // Every photon within `sqrt(radius_squared)` of `p`.
+    pub fn within(&self, p: Point3, radius_squared: f64) -> Vec<&Photon> {
+        let mut found = Vec::new();
+        let mut r2 = radius_squared;
+
+        self.search(0, self.photons.len(), p, &mut r2, &mut |_, i, _| {
+            found.push(i)
+        });
+
+        found.iter().map(|&i| &self.photons[i]).collect()
+    }
+
+    fn search<F>(&self, lo: usize, hi: usize, p: Point3, r2: &mut f64, visit: &mut F)
+    where
+        F: FnMut(f64, usize, &mut f64),
+    {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let axis = self.axes[mid];
+        let delta = p[axis] - self.photons[mid].p[axis];
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+
+        self.search(near.0, near.1, p, r2, visit);
+
+        let dist_squared = (self.photons[mid].p - p).length_squared();
+        if dist_squared < *r2 {
+            visit(dist_squared, mid, r2);
+        }
+
+        if delta * delta < *r2 {
+            self.search(far.0, far.1, p, r2, visit);
+        }
+    }
+}
+
+fn build(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let mut min = photons[0].p;
+    let mut max = photons[0].p;
+    for photon in photons.iter() {
+        for c in 0..3 {
+            min[c] = min[c].min(photon.p[c]);
+            max[c] = max[c].max(photon.p[c]);
+        }
+    }
+
+    let extent = max - min;
+    let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+        0
+    } else if extent.y() > extent.z() {
+        1
+    } else {
+        2
+    };
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    axes[mid] = axis;
+
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+// Shoots `count` photons and returns every landing, with powers normalized so
+// that summing over all of them estimates flux.
+pub fn shoot_photons(
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    count: usize,
+    max_depth: i32,
+) -> Vec<Photon> {
+    (0..count)
+        .into_par_iter()
+        .flat_map_iter(|_| trace_photon(world, lights, count, max_depth))
+        .collect()
+}
+
+fn trace_photon(
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    count: usize,
+    max_depth: i32,
+) -> Vec<Photon> {
+    let mut landings = Vec::new();
+
+    let time = sampler::random_double();
+    let Some((mut r, power)) = emit_photon(world, lights, time) else {
+        return landings;
+    };
+
+    let mut power = power / count as f64;
+    let mut specular_path = true;
+
+    for bounces in 0..max_depth {
+        let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+            break;
+        };
+
+        // The medium crossed on the way absorbs part of the photon's power.
+        power = power * r.transmittance(rec.t);
+
+        let (scattered, weight) = match rec.mat.scatter(r, &rec) {
+            None => break,
+            Some(ScatterRecord::SkipPdf { attenuation, ray }) => (*ray, attenuation),
+            Some(ScatterRecord::Pdf { pdf_ptr, .. }) => {
+                if !rec.mat.is_volumetric() {
+                    landings.push(Photon {
+                        p: rec.p,
+                        n: rec.normal,
+                        wi: -vec3::unit_vector(r.direction()),
+                        power,
+                        bounces,
+                        caustic: specular_path && bounces > 0,
+                    });
+                }
+                specular_path = false;
+
+                let Some(direction) = pdf_ptr.generate() else {
+                    break;
+                };
+                let scattered = rec.mat.scattered_ray(r, &rec, direction);
+                let pdf = pdf_ptr.value(scattered.direction());
+                if pdf <= 0.0 {
+                    break;
+                }
+
+                (scattered, rec.mat.eval(r, &rec, scattered) / pdf)
+            }
+        };
+
+        // Russian roulette keeps photon powers roughly constant.
+        let survival = weight.x().max(weight.y()).max(weight.z()).min(1.0);
+        if survival <= 0.0 || sampler::random_double() > survival {
+            break;
+        }
+
+        power = power * weight / survival;
+        r = scattered;
+    }
+
+    landings
+}
+
+// A photon's ray and power, leaving either the light list's geometry or one
+// of its sources, chosen in proportion to their power.
+fn emit_photon(world: &dyn Hittable, lights: &dyn Hittable, time: f64) -> Option<(Ray, Color)> {
+    let bounds = world.bounding_box();
+    let sources = lights.light_sources();
+    let area_power = lights.power();
+    let total = sources
+        .iter()
+        .fold(area_power, |sum, source| sum + source.power(&bounds));
+
+    if total > 0.0 {
+        let mut u = sampler::random_double() * total;
+        for source in sources {
+            let power = source.power(&bounds);
+            if u < power {
+                let (r, flux) = source.sample_le(&bounds, time)?;
+                return Some((r, flux * total / power));
+            }
+            u -= power;
+        }
+    }
+
+    let selection = if total > 0.0 { area_power / total } else { 1.0 };
+    let (light, pdf_pos) = lights.random_on_surface()?;
+    let emission = EmissionPdf::new(light.normal, light.mat.emission_sides());
+    let direction = emission.generate()?;
+    let pdf_dir = emission.value(direction);
+    if selection <= 0.0 || pdf_pos <= 0.0 || pdf_dir <= 0.0 {
+        return None;
+    }
+
+    let cosine = vec3::dot(light.normal, vec3::unit_vector(direction)).abs();
+    let flux = light.emitted_towards(direction, time) * cosine / (pdf_pos * pdf_dir * selection);
+
+    Some((Ray::new(light.p, direction, time), flux))
+}
+
+// Reflected radiance at `rec` from the photons around it, using the `k`
+// nearest within `max_radius`.
+fn radiance_estimate(
+    map: &PhotonMap,
+    r_in: Ray,
+    rec: &HitRecord,
+    k: usize,
+    max_radius: f64,
+) -> Color {
+    let (photons, radius_squared) = map.nearest(rec.p, k, max_radius * max_radius);
+
+    flux_estimate(&photons, r_in, rec) / (f64::consts::PI * radius_squared)
+}
+
+// Sum of BSDF-weighted photon powers arriving at `rec`.
+fn flux_estimate(photons: &[&Photon], r_in: Ray, rec: &HitRecord) -> Color {
+    photons
+        .iter()
+        .fold(Color::new(0.0, 0.0, 0.0), |sum, photon| {
+            let cosine = vec3::dot(rec.normal, photon.wi);
+            if cosine <= 0.0 || vec3::dot(rec.normal, photon.n) <= 0.0 {
+                return sum;
+            }
+
+            // `eval` includes the cosine, which the photon power already carries.
+            let towards_photon = r_in.continued(rec.p, photon.wi);
+            sum + rec.mat.eval(r_in, rec, towards_photon) * photon.power / cosine
+        })
+}
+
+// One-sample direct lighting at `rec` through the lights' sampling strategy.
+fn direct_lighting(
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    r_in: Ray,
+    rec: &HitRecord,
+) -> Color {
+    let from_sources = light::direct_lighting(world, lights, r_in, rec);
+
+    let direction = lights.random(rec.p);
+    let pdf = lights.pdf_value(rec.p, direction);
+    if pdf <= 0.0 {
+        return from_sources;
+    }
+
+    let shadow = r_in.continued(rec.p, direction);
+    let le = match world.hit(&shadow, Interval::new(0.001, f64::INFINITY)) {
+        Some(light) => light.mat.emitted(shadow, &light, light.u, light.v, light.p),
+        None => light::environment(lights, direction),
+    };
+
+    from_sources + rec.mat.eval(r_in, rec, shadow) * le / pdf
+}
+
+// Light from the camera's background reaching `rec` directly, found by
+// sampling the BSDF. Backgrounds emit no photons, so progressive photon
+// mapping sees no other light from them.
+fn background_lighting(
+    cam: &Camera,
+    world: &dyn Hittable,
+    r_in: Ray,
+    rec: &HitRecord,
+    pdf: &dyn Pdf,
+) -> Color {
+    let Some(direction) = pdf.generate() else {
+        return Color::new(0.0, 0.0, 0.0);
+    };
+    let scattered = r_in.continued(rec.p, direction);
+    let pdf_value = pdf.value(scattered.direction());
+    let background = cam.background(scattered.direction(), false);
+    if pdf_value <= 0.0 || background.length_squared() == 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    match world.hit(&scattered, Interval::new(0.001, f64::INFINITY)) {
+        Some(_) => Color::new(0.0, 0.0, 0.0),
+        None => rec.mat.eval(r_in, rec, scattered) * background / pdf_value,
+    }
+}
+
+struct Maps {
+    global: PhotonMap,
+    caustic: PhotonMap,
+    nearest: usize,
+    radius: f64,
+}
+
+pub fn render(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    photons: usize,
+    nearest: usize,
+    radius: f64,
+) -> Vec<Color> {
+    let landings = shoot_photons(world, lights, photons, cam.max_depth());
+    let caustic: Vec<Photon> = landings.iter().filter(|p| p.caustic).copied().collect();
+    eprintln!(
+        "Photons stored: {} global, {} caustic",
+        landings.len(),
+        caustic.len()
+    );
+
+    let maps = Maps {
+        global: PhotonMap::new(landings),
+        caustic: PhotonMap::new(caustic),
+        nearest,
+        radius,
+    };
+
+    cam.render_pixels(|i, j| {
+        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+        for s_j in 0..cam.sqrt_spp() {
+            for s_i in 0..cam.sqrt_spp() {
+                let r = cam.get_ray(i, j, s_i, s_j);
+                pixel_color += photon_color(cam, r, cam.max_depth(), world, lights, &maps);
+            }
+        }
+
+        cam.pixel_samples_scale() * pixel_color
+    })
+}
+
+// Follows specular bounces from the camera; at the first diffuse surface the
+// radiance is split into direct lighting, caustics from the caustic map and
+// indirect light through a final gather into the global map.
+fn photon_color(
+    cam: &Camera,
+    r: Ray,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    maps: &Maps,
+) -> Color {
+    if depth <= 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+        let camera_ray = depth == cam.max_depth();
+        return cam.background(r.direction(), camera_ray)
+            + light::environment(lights, r.direction());
+    };
+
+    let color_from_emission = rec.mat.emitted(r, &rec, rec.u, rec.v, rec.p);
+
+    let color = match rec.mat.scatter(r, &rec) {
+        None => color_from_emission,
+        Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+            color_from_emission
+                + attenuation * photon_color(cam, *ray, depth - 1, world, lights, maps)
+        }
+        Some(ScatterRecord::Pdf { pdf_ptr, .. }) => {
+            let direct = direct_lighting(world, lights, r, &rec);
+            let caustics = if rec.mat.is_volumetric() {
+                Color::new(0.0, 0.0, 0.0)
+            } else {
+                radiance_estimate(&maps.caustic, r, &rec, maps.nearest, maps.radius)
+            };
+
+            let indirect = match pdf_ptr.generate() {
+                Some(direction) => {
+                    let gathered = rec.mat.scattered_ray(r, &rec, direction);
+                    let pdf = pdf_ptr.value(gathered.direction());
+                    if pdf > 0.0 {
+                        rec.mat.eval(r, &rec, gathered)
+                            * gather(cam, gathered, depth - 1, world, maps)
+                            / pdf
+                    } else {
+                        Color::new(0.0, 0.0, 0.0)
+                    }
+                }
+                None => Color::new(0.0, 0.0, 0.0),
+            };
+
+            color_from_emission + direct + caustics + indirect
+        }
+    };
+
+    // The medium crossed on the way absorbs part of whatever the ray finds.
+    r.transmittance(rec.t) * color
+}
+
+// Radiance arriving along a final gather ray, read from the global map at the
+// first diffuse surface. Emitters and the environment are skipped: direct
+// light and caustics are already accounted for at the gathering point. The
+// camera's background is not, since it emits no photons.
+fn gather(cam: &Camera, r: Ray, depth: i32, world: &dyn Hittable, maps: &Maps) -> Color {
+    if depth <= 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+        return cam.background(r.direction(), false);
+    };
+
+    let color = match rec.mat.scatter(r, &rec) {
+        Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+            attenuation * gather(cam, *ray, depth - 1, world, maps)
+        }
+        Some(ScatterRecord::Pdf { .. }) if !rec.mat.is_volumetric() => {
+            radiance_estimate(&maps.global, r, &rec, maps.nearest, maps.radius)
+        }
+        _ => Color::new(0.0, 0.0, 0.0),
+    };
+
+    // The medium crossed on the way absorbs part of whatever the ray finds.
+    r.transmittance(rec.t) * color
+}
+
+// Per-pixel SPPM statistics.
+struct PixelState {
+    radius_squared: f64,
+    photon_count: f64,
+    tau: Color,    // accumulated BSDF-weighted flux inside the radius
+    direct: Color, // sum of emission and direct lighting over iterations
+}
+
+pub fn render_progressive(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    iterations: i32,
+    photons: usize,
+    radius: f64,
+) -> Vec<Color> {
+    let width = cam.image_width();
+    let height = cam.image_height();
+    let strata = cam.sqrt_spp() * cam.sqrt_spp();
+    let mut pixels: Vec<PixelState> = (0..width * height)
+        .map(|_| PixelState {
+            radius_squared: radius * radius,
+            photon_count: 0.0,
+            tau: Color::new(0.0, 0.0, 0.0),
+            direct: Color::new(0.0, 0.0, 0.0),
+        })
+        .collect();
+
+    for iteration in 0..iterations {
+        let stratum = iteration % strata;
+        let (s_i, s_j) = (stratum % cam.sqrt_spp(), stratum / cam.sqrt_spp());
+
+        // Photons that reach a surface straight from a light are left out:
+        // direct lighting is sampled explicitly at the visible points.
+        let landings = shoot_photons(world, lights, photons, cam.max_depth());
+        let map = PhotonMap::new(landings.into_iter().filter(|p| p.bounces > 0).collect());
+
+        pixels
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, pixel)| {
+                let i = index as i32 % width;
+                let j = index as i32 / width;
+                let r = cam.get_ray(i, j, s_i, s_j);
+                let (direct, visible) = visible_point(cam, r, cam.max_depth(), world, lights);
+                pixel.direct += direct;
+
+                let Some((r_in, rec, beta)) = visible else {
+                    return;
+                };
+
+                let found = map.within(rec.p, pixel.radius_squared);
+                if found.is_empty() {
+                    return;
+                }
+
+                // Keep 2/3 of the new photons and shrink the radius to match.
+                let m = found.len() as f64;
+                let phi = beta * flux_estimate(&found, r_in, &rec);
+                let count = pixel.photon_count + (2.0 / 3.0) * m;
+                let radius_squared = pixel.radius_squared * count / (pixel.photon_count + m);
+
+                pixel.tau = (pixel.tau + phi) * (radius_squared / pixel.radius_squared);
+                pixel.photon_count = count;
+                pixel.radius_squared = radius_squared;
+            });
+
+        eprintln!("Iteration {} of {}", iteration + 1, iterations);
+    }
+
+    let n = iterations as f64;
+    pixels
+        .iter()
+        .map(|pixel| pixel.direct / n + pixel.tau / (n * f64::consts::PI * pixel.radius_squared))
+        .collect()
+}
+
+// Traces a camera ray through specular bounces. Returns the emitted and
+// directly lit radiance picked up on the way and, if it ends on a diffuse
+// surface, the visible point with the throughput that reaches it.
+fn visible_point<'a>(
+    cam: &Camera,
+    r: Ray,
+    depth: i32,
+    world: &'a dyn Hittable,
+    lights: &dyn Hittable,
+) -> (Color, Option<(Ray, HitRecord<'a>, Color)>) {
+    let mut r = r;
+    let mut beta = Color::new(1.0, 1.0, 1.0);
+    let mut radiance = Color::new(0.0, 0.0, 0.0);
+
+    for bounce in 0..depth {
+        let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+            let background = cam.background(r.direction(), bounce == 0);
+            radiance += beta * (background + light::environment(lights, r.direction()));
+            break;
+        };
+
+        // The medium crossed on the way absorbs part of the throughput.
+        beta = beta * r.transmittance(rec.t);
+        radiance += beta * rec.mat.emitted(r, &rec, rec.u, rec.v, rec.p);
+
+        match rec.mat.scatter(r, &rec) {
+            Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+                beta = beta * attenuation;
+                r = *ray;
+            }
+            Some(ScatterRecord::Pdf { pdf_ptr, .. }) => {
+                radiance += beta * direct_lighting(world, lights, r, &rec);
+                radiance += beta * background_lighting(cam, world, r, &rec, pdf_ptr.as_ref());
+
+                if rec.mat.is_volumetric() {
+                    break;
+                }
+
+                return (radiance, Some((r, rec, beta)));
+            }
+            None => break,
+        }
+    }
+
+    (radiance, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn photon_at(p: Point3) -> Photon {
+        Photon {
+            p,
+            n: Vec3::new(0.0, 1.0, 0.0),
+            wi: Vec3::new(0.0, 1.0, 0.0),
+            power: Color::new(1.0, 1.0, 1.0),
+            bounces: 0,
+            caustic: false,
+        }
+    }
+
+    fn random_points(n: usize) -> Vec<Point3> {
+        (0..n).map(|_| vec3::random_range(-10.0, 10.0)).collect()
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let points = random_points(2000);
+        let map = PhotonMap::new(points.iter().map(|&p| photon_at(p)).collect());
+
+        for query in random_points(50) {
+            let k = 25;
+            let (found, radius_squared) = map.nearest(query, k, f64::INFINITY);
+
+            let mut expected: Vec<f64> = points
+                .iter()
+                .map(|&p| (p - query).length_squared())
+                .collect();
+            expected.sort_by(f64::total_cmp);
+
+            let mut distances: Vec<f64> = found
+                .iter()
+                .map(|photon| (photon.p - query).length_squared())
+                .collect();
+            distances.sort_by(f64::total_cmp);
+
+            assert_eq!(distances, expected[..k]);
+            assert_eq!(radius_squared, expected[k - 1]);
+        }
+    }
+
+    #[test]
+    fn nearest_stops_at_the_maximum_distance() {
+        let points = random_points(500);
+        let map = PhotonMap::new(points.iter().map(|&p| photon_at(p)).collect());
+        let query = Point3::new(0.0, 0.0, 0.0);
+
+        let (found, radius_squared) = map.nearest(query, 1000, 4.0);
+        let expected = points
+            .iter()
+            .filter(|&&p| (p - query).length_squared() < 4.0)
+            .count();
+
+        assert_eq!(found.len(), expected);
+        assert_eq!(radius_squared, 4.0);
+    }
+
+    #[test]
+    fn within_finds_every_photon_in_the_radius() {
+        let points = random_points(1000);
+        let map = PhotonMap::new(points.iter().map(|&p| photon_at(p)).collect());
+
+        for query in random_points(20) {
+            let found = map.within(query, 9.0);
+            let expected = points
+                .iter()
+                .filter(|&&p| (p - query).length_squared() < 9.0)
+                .count();
+
+            assert_eq!(found.len(), expected);
+            assert!(found.iter().all(|ph| (ph.p - query).length_squared() < 9.0));
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use core::f64;
-
-use rand::Rng;
+use std::sync::OnceLock;
 
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{self, HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::interval::Interval;
+use crate::light::DirectionCone;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{self, Point3, Vec3};
@@ -20,16 +20,18 @@ pub struct Quad {
     normal: Vec3,
     D: f64, // plane equation constant
     area: f64,
+    coverage: OnceLock<f64>,
 }
 
 impl Quad {
-    pub fn new(Q: Point3, u: Vec3, v: Vec3, mat: Box<dyn Material>) -> Self {
+    pub fn new(Q: Point3, u: Vec3, v: Vec3, mut mat: Box<dyn Material>) -> Self {
         let bbox = set_bounding_box(Q, u, v);
         let n = vec3::cross(u, v);
         let normal = vec3::unit_vector(n);
         let D = vec3::dot(normal, Q);
         let w = n / vec3::dot(n, n);
         let area = n.length();
+        mat.set_emitter_area(area);
 
         Self {
             Q,
@@ -41,8 +43,25 @@ impl Quad {
             normal,
             D,
             area,
+            coverage: OnceLock::new(),
         }
     }
+
+    fn record_at(&self, alpha: f64, beta: f64) -> HitRecord<'_> {
+        let p = self.Q + (alpha * self.u) + (beta * self.v);
+
+        HitRecord::new(p, self.normal, self.mat.as_ref(), 0.0, alpha, beta, true)
+            .with_tangents(self.u, self.v)
+    }
+
+    // Area left by the material's opacity, worked out the first time a light
+    // sample needs it.
+    fn visible_area(&self) -> f64 {
+        self.area
+            * *self
+                .coverage
+                .get_or_init(|| hittable::opaque_fraction(|a, b| self.record_at(a, b)))
+    }
 }
 
 impl Hittable for Quad {
@@ -74,7 +93,7 @@ impl Hittable for Quad {
             -self.normal
         };
 
-        Some(HitRecord::new(
+        let rec = HitRecord::new(
             intersection,
             normal,
             self.mat.as_ref(),
@@ -82,13 +101,18 @@ impl Hittable for Quad {
             alpha,
             beta,
             front_face,
-        ))
+        )
+        .with_tangents(self.u, self.v);
+
+        Some(rec).filter(|rec| rec.is_opaque())
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
 
+    // Both sides are sampled alike, so that light sampling stays unbiased
+    // whichever sides the material emits from (see `EmissionSides`).
     fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
         if let Some(rec) = self.hit(
             &Ray::new(origin, direction, 0.0),
@@ -97,18 +121,49 @@ impl Hittable for Quad {
             let distance_squared = rec.t * rec.t * direction.length_squared();
             let cosine = (vec3::dot(direction, rec.normal) / direction.length()).abs();
 
-            distance_squared / (cosine * self.area)
+            distance_squared / (cosine * self.visible_area())
         } else {
             0.0
         }
     }
 
     fn random(&self, origin: Point3) -> Vec3 {
-        let p = self.Q
-            + (rand::rng().random::<f64>() * self.u)
-            + (rand::rng().random::<f64>() * self.v);
+        match hittable::random_opaque(|a, b| self.record_at(a, b)) {
+            Some(rec) => rec.p - origin,
+            None => self.Q - origin,
+        }
+    }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        let rec = hittable::random_opaque(|a, b| self.record_at(a, b))?;
+
+        Some((rec, 1.0 / self.visible_area()))
+    }
+
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self
+            .hit(
+                &Ray::new(origin, direction, 0.0),
+                Interval::new(0.001, f64::INFINITY),
+            )
+            .is_some()
+        {
+            1.0 / self.visible_area()
+        } else {
+            0.0
+        }
+    }
+
+    fn power(&self) -> f64 {
+        hittable::emitted_power(self.area, |a, b| self.record_at(a, b))
+    }
+
+    fn emits(&self) -> bool {
+        self.mat.is_emissive()
+    }
 
-        p - origin
+    fn emission_cone(&self) -> DirectionCone {
+        DirectionCone::emitted_from(self.normal, self.mat.emission_sides())
     }
 }
 
@@ -1,15 +1,44 @@
+use crate::color::Color;
 use crate::vec3::{Point3, Vec3};
 
+// How many nested dielectrics a ray keeps track of being inside.
+const MAX_NESTING: usize = 4;
+
+// A dielectric medium a ray has entered and not yet left. `id` tells the
+// material instances apart; of overlapping media, the one with the highest
+// `priority` is the one actually present.
+#[derive(Debug, Clone, Copy)]
+pub struct Interior {
+    pub id: usize,
+    pub priority: i32,
+    pub refraction_index: f64,
+    pub absorption: Color,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
     tm: f64,
+    wavelength: Option<f64>,
+    interior: [Option<Interior>; MAX_NESTING],
 }
 
 impl Ray {
     pub fn new(orig: Point3, dir: Vec3, tm: f64) -> Self {
-        Ray { orig, dir, tm }
+        Ray {
+            orig,
+            dir,
+            tm,
+            wavelength: None,
+            interior: [None; MAX_NESTING],
+        }
+    }
+
+    // The next segment of the same path: a new origin and direction, with
+    // the time, wavelength and media carried over.
+    pub fn continued(&self, orig: Point3, dir: Vec3) -> Self {
+        Self { orig, dir, ..*self }
     }
 
     pub fn origin(&self) -> Point3 {
@@ -27,4 +56,65 @@ impl Ray {
     pub fn time(&self) -> f64 {
         self.tm
     }
+
+    // Wavelength in nm that the ray carries in spectral mode, which is all
+    // a wavelength dependent material needs to know of it.
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Self {
+        Self {
+            wavelength: Some(wavelength),
+            ..self
+        }
+    }
+
+    // The medium the ray travels through, if any: the highest priority one
+    // it is inside, the most recently entered on ties.
+    pub fn medium(&self) -> Option<Interior> {
+        self.interior
+            .iter()
+            .flatten()
+            .copied()
+            .reduce(|a, b| if b.priority >= a.priority { b } else { a })
+    }
+
+    // Beer-Lambert transmittance through the ray's medium up to `at(t)`,
+    // whatever ends the segment there.
+    pub fn transmittance(&self, t: f64) -> Color {
+        let Some(medium) = self.medium() else {
+            return Color::new(1.0, 1.0, 1.0);
+        };
+
+        let distance = t * self.dir.length();
+        let transmittance = |sigma: f64| (-sigma * distance).exp();
+
+        Color::new(
+            transmittance(medium.absorption.x()),
+            transmittance(medium.absorption.y()),
+            transmittance(medium.absorption.z()),
+        )
+    }
+
+    // Once the list is full, further media are ignored.
+    pub fn entering(self, medium: Interior) -> Self {
+        let mut interior = self.interior;
+        if let Some(slot) = interior.iter_mut().find(|m| m.is_none()) {
+            *slot = Some(medium);
+        }
+
+        Self { interior, ..self }
+    }
+
+    // Media stay in the order they were entered.
+    pub fn leaving(self, id: usize) -> Self {
+        let mut interior = self.interior;
+        if let Some(i) = interior.iter().rposition(|m| m.is_some_and(|m| m.id == id)) {
+            interior[i..].rotate_left(1);
+            interior[MAX_NESTING - 1] = None;
+        }
+
+        Self { interior, ..self }
+    }
 }
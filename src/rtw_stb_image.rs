@@ -13,21 +13,32 @@ impl ImageTexture {
 
         Self { image }
     }
-}
 
-impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
-        if self.image.height() <= 0 {
-            return Color::new(0.0, 1.0, 1.0);
+    // The image's alpha channel as a grey texture, for `Cutout` masks.
+    pub fn alpha(self) -> AlphaTexture {
+        AlphaTexture { image: self }
+    }
+
+    fn texel(&self, u: f64, v: f64) -> Option<[u8; 4]> {
+        if self.image.height() == 0 {
+            return None;
         }
 
         let u = u.clamp(0.0, 1.0);
         let v = 1.0 - v.clamp(0.0, 1.0);
 
         let i = ((u * self.image.width() as f64) as u32).min(self.image.width() - 1);
-        let j = ((v * self.image.height() as f64) as u32).min(self.image.width() - 1);
-        let pixel = self.image.get_pixel(i, j);
-        let [r, g, b, _] = pixel.0;
+        let j = ((v * self.image.height() as f64) as u32).min(self.image.height() - 1);
+
+        Some(self.image.get_pixel(i, j).0)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        let Some([r, g, b, _]) = self.texel(u, v) else {
+            return Color::new(0.0, 1.0, 1.0);
+        };
 
         let color_scale = 1.0 / 255.0;
         Color::new(
@@ -37,3 +48,18 @@ impl Texture for ImageTexture {
         )
     }
 }
+
+pub struct AlphaTexture {
+    image: ImageTexture,
+}
+
+impl Texture for AlphaTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        let Some([_, _, _, a]) = self.image.texel(u, v) else {
+            return Color::new(1.0, 1.0, 1.0);
+        };
+
+        let a = a as f64 / 255.0;
+        Color::new(a, a, a)
+    }
+}
@@ -0,0 +1,174 @@
+use core::f64;
+use std::cell::RefCell;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::vec3::Point3;
+
+// Every random number the renderer consumes goes through `random_double`.
+// Normally that is the thread's RNG, but an integrator can install a vector
+// of primary samples on the current thread so that the same path tracing
+// code is driven by numbers it controls (see `mlt`).
+
+thread_local! {
+    static PRIMARY_SAMPLES: RefCell<Option<PrimarySamples>> = const { RefCell::new(None) };
+}
+
+pub fn random_double() -> f64 {
+    PRIMARY_SAMPLES.with(|samples| match samples.borrow_mut().as_mut() {
+        Some(samples) => samples.next(),
+        None => rand::rng().random(),
+    })
+}
+
+pub fn random_double_range(min: f64, max: f64) -> f64 {
+    min + (max - min) * random_double()
+}
+
+// Returns a random integer in [min, max].
+pub fn random_int(min: usize, max: usize) -> usize {
+    let n = max - min + 1;
+
+    min + ((random_double() * n as f64) as usize).min(n - 1)
+}
+
+// A number in [0, 1) fixed by the point `p`, for random choices that must
+// come out the same whenever the point is looked at. Coordinates are rounded
+// first so that the same point computed two ways still agrees.
+pub fn hash_point(p: Point3) -> f64 {
+    let mut hasher = DefaultHasher::new();
+    for x in [p.x(), p.y(), p.z()] {
+        ((x * 1e6).round() as i64).hash(&mut hasher);
+    }
+
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Runs `f` with `samples` supplying its random numbers from the start of the
+// vector, then hands the samples back.
+pub fn with_primary_samples<R>(
+    mut samples: PrimarySamples,
+    f: impl FnOnce() -> R,
+) -> (R, PrimarySamples) {
+    samples.sample_index = 0;
+    PRIMARY_SAMPLES.with(|installed| *installed.borrow_mut() = Some(samples));
+
+    let result = f();
+    let samples = PRIMARY_SAMPLES.with(|installed| installed.borrow_mut().take().unwrap());
+
+    (result, samples)
+}
+
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+    value: f64,
+    last_modification_iteration: i64,
+    value_backup: f64,
+    modify_backup: i64,
+}
+
+// A point in primary sample space with Kelemen-style mutations: a large step
+// replaces every number, a small step perturbs each one by a normal offset.
+// Numbers are mutated lazily when first read in an iteration, catching up on
+// the small steps they missed.
+pub struct PrimarySamples {
+    rng: StdRng,
+    sigma: f64,
+    large_step_probability: f64,
+    x: Vec<PrimarySample>,
+    current_iteration: i64,
+    large_step: bool,
+    last_large_step_iteration: i64,
+    sample_index: usize,
+}
+
+impl PrimarySamples {
+    pub fn new(seed: u64, sigma: f64, large_step_probability: f64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            sigma,
+            large_step_probability,
+            x: Vec::new(),
+            current_iteration: 0,
+            large_step: true,
+            last_large_step_iteration: 0,
+            sample_index: 0,
+        }
+    }
+
+    pub fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.random::<f64>() < self.large_step_probability;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    pub fn reject(&mut self) {
+        for xi in self.x.iter_mut() {
+            if xi.last_modification_iteration == self.current_iteration {
+                xi.value = xi.value_backup;
+                xi.last_modification_iteration = xi.modify_backup;
+            }
+        }
+
+        self.current_iteration -= 1;
+    }
+
+    fn next(&mut self) -> f64 {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        self.ensure_ready(index);
+
+        self.x[index].value
+    }
+
+    fn ensure_ready(&mut self, index: usize) {
+        // Numbers the current path never read are as uniform as the rest.
+        // Starting them at zero would leave small steps stuck near it, and
+        // rejection sampling loops would never finish.
+        while index >= self.x.len() {
+            self.x.push(PrimarySample {
+                value: self.rng.random(),
+                last_modification_iteration: self.last_large_step_iteration,
+                ..PrimarySample::default()
+            });
+        }
+
+        let xi = &mut self.x[index];
+
+        // Numbers untouched since before the last accepted large step are
+        // stale: that step would have replaced them.
+        if xi.last_modification_iteration < self.last_large_step_iteration {
+            xi.value = self.rng.random();
+            xi.last_modification_iteration = self.last_large_step_iteration;
+        }
+
+        xi.value_backup = xi.value;
+        xi.modify_backup = xi.last_modification_iteration;
+
+        if self.large_step {
+            xi.value = self.rng.random();
+        } else {
+            let n_small = self.current_iteration - xi.last_modification_iteration;
+            let effective_sigma = self.sigma * (n_small as f64).sqrt();
+            xi.value += standard_normal(&mut self.rng) * effective_sigma;
+            xi.value -= xi.value.floor();
+        }
+
+        xi.last_modification_iteration = self.current_iteration;
+    }
+}
+
+// Box-Muller transform.
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * f64::consts::PI * u2).cos()
+}
@@ -0,0 +1,231 @@
+use core::f64;
+
+use crate::color::Color;
+use crate::environment::EnvironmentLight;
+use crate::light::DirectionalLight;
+use crate::material::LUMINOUS_EFFICACY;
+use crate::spectrum;
+use crate::vec3::{self, Vec3};
+
+// A clear daylight sky after Preetham, Shirley and Smits, "A Practical
+// Analytic Model for Daylight" (1999). The sky's luminance and chromaticity
+// follow Perez et al.'s formula, fitted to the sun's position and the
+// atmosphere's turbidity: 2 for a very clear sky, around 3 for a typical
+// one and up to 10 for haze. Below the horizon is flat ground of a given
+// albedo, lit by the sun and the sky.
+//
+// The sky and the sun are two lights: `environment` bakes the sky into an
+// environment light that rays escaping the scene see, and `sun` is a
+// directional light the size of the sun's disk. Both are in physical units,
+// like `DiffuseLight::with_radiance`; `with_intensity` scales them to suit
+// the camera.
+
+// Resolution the sky is baked at. It varies slowly everywhere but around the
+// sun, whose disk is the directional light.
+const SKY_WIDTH: usize = 512;
+const SKY_HEIGHT: usize = 256;
+
+// Extraterrestrial sunlight, as a blackbody at the sun's surface temperature
+// delivering the solar illuminance constant (lux).
+const SUN_TEMPERATURE: f64 = 5778.0;
+const SOLAR_ILLUMINANCE: f64 = 128_000.0;
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+pub struct Sky {
+    to_sun: Vec3,
+    theta_sun: f64,
+    // Perez coefficients A to E for luminance Y and chromaticities x and y,
+    // with the zenith values over the formula's value at the zenith.
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    sun_irradiance: Color,
+    sky_irradiance: Color,
+    ground_albedo: Color,
+    intensity: f64,
+}
+
+impl Sky {
+    // The sun `elevation` degrees above the horizon (0 to 90) and `azimuth`
+    // degrees round from the -z axis towards +x.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let theta_sun = 0.5 * f64::consts::PI - elevation;
+        let to_sun = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (f64::consts::PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = zenith_chromaticity(
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+            t,
+            theta_sun,
+        );
+        let zenith_y = zenith_chromaticity(
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+            t,
+            theta_sun,
+        );
+
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        let zenith = std::array::from_fn(|i| zenith[i] / perez_formula(&perez[i], 1.0, theta_sun));
+
+        let mut sky = Self {
+            to_sun,
+            theta_sun,
+            perez,
+            zenith,
+            sun_irradiance: sun_irradiance(theta_sun, turbidity),
+            sky_irradiance: Color::new(0.0, 0.0, 0.0),
+            ground_albedo: Color::new(0.3, 0.3, 0.3),
+            intensity: 1.0,
+        };
+        sky.sky_irradiance = sky.horizontal_sky_irradiance();
+
+        sky
+    }
+
+    pub fn with_ground_albedo(self, ground_albedo: Color) -> Self {
+        Self {
+            ground_albedo,
+            ..self
+        }
+    }
+
+    // Scales the sky and the sun.
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        Self { intensity, ..self }
+    }
+
+    // Radiance arriving from `direction`, leaving out the sun's disk.
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let d = vec3::unit_vector(direction);
+        let radiance = if d.y() > 0.0 {
+            self.sky_radiance(d)
+        } else {
+            let irradiance = self.sun_irradiance * self.theta_sun.cos() + self.sky_irradiance;
+            self.ground_albedo * irradiance / f64::consts::PI
+        };
+
+        self.intensity * radiance
+    }
+
+    pub fn environment(&self) -> EnvironmentLight {
+        EnvironmentLight::from_fn(SKY_WIDTH, SKY_HEIGHT, |direction| self.radiance(direction))
+    }
+
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(self.to_sun, self.intensity * self.sun_irradiance)
+            .with_angular_diameter(SUN_ANGULAR_DIAMETER)
+    }
+
+    fn sky_radiance(&self, d: Vec3) -> Color {
+        let gamma = vec3::dot(d, self.to_sun).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y]: [f64; 3] =
+            std::array::from_fn(|i| self.zenith[i] * perez_formula(&self.perez[i], d.y(), gamma));
+
+        // Luminance is in kcd/m^2.
+        let xyz = Color::new(x / y, 1.0, (1.0 - x - y) / y) * luminance;
+        let rgb = spectrum::xyz_to_rgb(xyz) * 1000.0 / LUMINOUS_EFFICACY;
+
+        rgb.clamp(0.0, f64::INFINITY)
+    }
+
+    // Irradiance from the sky on the horizontal ground, by the midpoint rule.
+    fn horizontal_sky_irradiance(&self) -> Color {
+        let (n_theta, n_phi) = (64, 128);
+        let d_theta = 0.5 * f64::consts::PI / n_theta as f64;
+        let d_phi = 2.0 * f64::consts::PI / n_phi as f64;
+
+        let mut irradiance = Color::new(0.0, 0.0, 0.0);
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let d = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let solid_angle = theta.sin() * d_theta * d_phi;
+                irradiance += self.sky_radiance(d) * theta.cos() * solid_angle;
+            }
+        }
+
+        irradiance
+    }
+}
+
+// Perez et al.'s all-weather sky formula, for a view direction `acos(cos_theta)`
+// from the zenith and `gamma` from the sun.
+fn perez_formula(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+// Preetham et al.'s fit of the zenith chromaticity: [T^2, T, 1] m [θ^3, θ^2, θ, 1].
+fn zenith_chromaticity(m: [[f64; 4]; 3], turbidity: f64, theta_sun: f64) -> f64 {
+    let t = [turbidity * turbidity, turbidity, 1.0];
+    let theta = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+
+    (0..3)
+        .map(|i| t[i] * (0..4).map(|j| m[i][j] * theta[j]).sum::<f64>())
+        .sum()
+}
+
+// Direct sunlight on a surface facing the sun, after Rayleigh scattering and
+// aerosol extinction along the path through the atmosphere (Preetham et al.'s
+// appendix, leaving out the weaker ozone and water vapor bands).
+fn sun_irradiance(theta_sun: f64, turbidity: f64) -> Color {
+    let relative_air_mass =
+        1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let beta = 0.04608365822050 * turbidity - 0.04586025928522;
+
+    let transmitted = spectrum::spectrum_rgb(|lambda| {
+        let micrometers = lambda / 1000.0;
+        let rayleigh = 0.008735 * micrometers.powf(-4.08);
+        let aerosol = beta * micrometers.powf(-1.3);
+
+        spectrum::blackbody(lambda, SUN_TEMPERATURE)
+            * (-(rayleigh + aerosol) * relative_air_mass).exp()
+    });
+
+    SOLAR_ILLUMINANCE / LUMINOUS_EFFICACY * transmitted
+        / spectrum::blackbody_luminance(SUN_TEMPERATURE)
+}
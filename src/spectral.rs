@@ -0,0 +1,147 @@
+use core::f64;
+
+use crate::camera::{self, Camera};
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light;
+use crate::material::ScatterRecord;
+use crate::pdf::HittablePdf;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::spectrum::{self, SampledSpectrum, SampledWavelengths};
+
+// The path tracer of `camera::ray_color` carrying a `SampledSpectrum` instead
+// of RGB. Materials and textures stay in RGB and are upsampled at the path's
+// wavelengths unless they describe themselves spectrally; rays are tagged
+// with the hero wavelength for materials that disperse light.
+
+pub fn render(cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable) -> Vec<Color> {
+    cam.render_pixels(|i, j| {
+        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+        for s_j in 0..cam.sqrt_spp() {
+            for s_i in 0..cam.sqrt_spp() {
+                let mut lambda = SampledWavelengths::sample_visible(sampler::random_double());
+                let r = cam.get_ray(i, j, s_i, s_j);
+                let l = spectral_color(cam, r, cam.max_depth(), world, lights, &mut lambda);
+                pixel_color += l.to_rgb(&lambda);
+            }
+        }
+
+        cam.pixel_samples_scale() * pixel_color
+    })
+}
+
+fn spectral_color(
+    cam: &Camera,
+    r: Ray,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    lambda: &mut SampledWavelengths,
+) -> SampledSpectrum {
+    if depth <= 0 {
+        return SampledSpectrum::new(0.0);
+    }
+
+    let r = r.with_wavelength(lambda.hero());
+    let Some(rec) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) else {
+        let camera_ray = depth == cam.max_depth();
+        let escaped =
+            cam.background(r.direction(), camera_ray) + light::environment(lights, r.direction());
+        return SampledSpectrum::from_illuminant(escaped, lambda);
+    };
+
+    // The medium crossed on the way absorbs part of whatever the ray finds.
+    let transmittance = r.transmittance(rec.t);
+    let transmittance =
+        SampledSpectrum::from_fn(lambda, |l| spectrum::rgb_to_spectrum(transmittance, l));
+
+    transmittance * spectral_hit_color(cam, r, &rec, depth, world, lights, lambda)
+}
+
+// Light leaving `rec` back along `r` at the wavelengths `lambda`.
+fn spectral_hit_color(
+    cam: &Camera,
+    r: Ray,
+    rec: &HitRecord,
+    depth: i32,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    lambda: &mut SampledWavelengths,
+) -> SampledSpectrum {
+    let color_from_emission = match rec.mat.spectral_emission(r, rec, lambda.hero()) {
+        Some(_) => SampledSpectrum::from_fn(lambda, |l| {
+            rec.mat.spectral_emission(r, rec, l).unwrap_or(0.0)
+        }),
+        None => {
+            let emitted = rec.mat.emitted(r, rec, rec.u, rec.v, rec.p);
+            SampledSpectrum::from_illuminant(emitted, lambda)
+        }
+    };
+
+    if rec.mat.is_dispersive() {
+        lambda.terminate_secondary();
+    }
+
+    match rec.mat.scatter(r, rec) {
+        None => color_from_emission,
+        Some(ScatterRecord::SkipPdf { attenuation, ray }) => {
+            let attenuation = to_spectrum(r, rec, *ray, attenuation, lambda);
+
+            attenuation * spectral_color(cam, *ray, depth - 1, world, lights, lambda)
+        }
+        Some(ScatterRecord::Pdf { pdf_ptr, .. }) => {
+            let light_pdf = HittablePdf::new(lights, rec.p);
+            let p = camera::light_mixture(&light_pdf, lights, pdf_ptr, r);
+            let mut color_from_sources = SampledSpectrum::new(0.0);
+            let sources = lights.light_sources().iter();
+            for source in sources.filter(|source| !source.is_environment()) {
+                if let Some(sample) = light::sample_visible(world, source.as_ref(), rec.p, r.time())
+                {
+                    let towards_light = r.continued(rec.p, sample.direction);
+                    let f = rec.mat.eval(r, rec, towards_light);
+                    let f = to_spectrum(r, rec, towards_light, f, lambda);
+                    color_from_sources +=
+                        f * SampledSpectrum::from_illuminant(sample.radiance, lambda);
+                }
+            }
+
+            // A direction nothing can scatter into ends the path.
+            let scattered = match p.generate() {
+                Some(direction) => rec.mat.scattered_ray(r, rec, direction),
+                None => return color_from_emission + color_from_sources,
+            };
+            let pdf_value = p.value(scattered.direction());
+            if pdf_value <= 0.0 {
+                return color_from_emission + color_from_sources;
+            }
+
+            let f = rec.mat.eval(r, rec, scattered);
+            let f = to_spectrum(r, rec, scattered, f, lambda);
+
+            let sample_color = spectral_color(cam, scattered, depth - 1, world, lights, lambda);
+            let color_from_scatter = f * sample_color / pdf_value;
+            let color_from_scatter = color_from_scatter.clamp(0.0, 30.0);
+
+            color_from_emission + color_from_sources + color_from_scatter
+        }
+    }
+}
+
+// The spectral counterpart of `rgb`, the attenuation of a `SkipPdf` record
+// or `eval` of a `Pdf` one, for the scattering from `r_in` into `scattered`.
+fn to_spectrum(
+    r_in: Ray,
+    rec: &HitRecord,
+    scattered: Ray,
+    rgb: Color,
+    lambda: &SampledWavelengths,
+) -> SampledSpectrum {
+    SampledSpectrum::from_fn(lambda, |l| {
+        rec.mat
+            .spectral_attenuation(r_in, rec, scattered, l)
+            .unwrap_or_else(|| spectrum::rgb_to_spectrum(rgb, l))
+    })
+}
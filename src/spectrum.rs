@@ -0,0 +1,369 @@
+use core::f64;
+use std::ops::{Add, AddAssign, Div, Mul};
+use std::sync::LazyLock;
+
+use crate::color::Color;
+
+// Spectral quantities for hero wavelength rendering (Wilkie et al. 2014):
+// each camera path carries `SPECTRUM_SAMPLES` wavelengths, a randomly drawn
+// hero and the others rotated evenly through the visible range, so one path
+// estimates several points of the spectrum at once.
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    lambda: [f64; SPECTRUM_SAMPLES],
+    pdf: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    // Wavelengths importance sampled towards where the eye is sensitive.
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; SPECTRUM_SAMPLES];
+        let mut pdf = [0.0; SPECTRUM_SAMPLES];
+
+        for i in 0..SPECTRUM_SAMPLES {
+            let up = (u + i as f64 / SPECTRUM_SAMPLES as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // Keeps only the hero wavelength, for scattering whose direction depends
+    // on wavelength so the others can't follow the same path.
+    pub fn terminate_secondary(&mut self) {
+        if self.is_secondary_terminated() {
+            return;
+        }
+
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.pdf.iter().skip(1).all(|&pdf| pdf == 0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SampledSpectrum {
+    values: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(c: f64) -> Self {
+        Self {
+            values: [c; SPECTRUM_SAMPLES],
+        }
+    }
+
+    pub fn from_fn<F: Fn(f64) -> f64>(lambda: &SampledWavelengths, f: F) -> Self {
+        Self {
+            values: lambda.lambda.map(f),
+        }
+    }
+
+    // Emission spectrum that appears as `rgb`: the reflectance shape lit by
+    // D65, the white point of sRGB.
+    pub fn from_illuminant(rgb: Color, lambda: &SampledWavelengths) -> Self {
+        if rgb.x() == 0.0 && rgb.y() == 0.0 && rgb.z() == 0.0 {
+            return Self::new(0.0);
+        }
+
+        Self::from_fn(lambda, |l| rgb_to_spectrum(rgb, l) * *D65_SCALE * d65(l))
+    }
+
+    pub fn clamp(&self, min: f64, max: f64) -> Self {
+        Self {
+            values: self.values.map(|v| v.clamp(min, max)),
+        }
+    }
+
+    // Back to linear sRGB through CIE XYZ, dividing out the probability of
+    // having chosen each wavelength.
+    pub fn to_rgb(self, lambda: &SampledWavelengths) -> Color {
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+
+        for i in 0..SPECTRUM_SAMPLES {
+            if lambda.pdf[i] == 0.0 {
+                continue;
+            }
+
+            let l = lambda.lambda[i];
+            xyz += Color::new(cie_x(l), cie_y(l), cie_z(l)) * self.values[i] / lambda.pdf[i];
+        }
+
+        xyz_to_rgb(xyz / (SPECTRUM_SAMPLES as f64 * *CIE_Y_INTEGRAL))
+    }
+}
+
+pub fn xyz_to_rgb(xyz: Color) -> Color {
+    Color::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            values: std::array::from_fn(|i| self.values[i] + rhs.values[i]),
+        }
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            values: std::array::from_fn(|i| self.values[i] * rhs.values[i]),
+        }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Self {
+            values: self.values.map(|v| v * rhs),
+        }
+    }
+}
+
+impl Div<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn div(self, rhs: f64) -> Self {
+        self * (1.0 / rhs)
+    }
+}
+
+// Sampling density proportional to the CIE Y curve, after PBRT.
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+// The CIE 1931 color matching functions as the multi-lobe Gaussian fit of
+// Wyman, Sloan and Shirley (2013).
+fn piecewise_gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma_low } else { sigma_high };
+
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_x(lambda: f64) -> f64 {
+    1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2)
+}
+
+pub fn cie_y(lambda: f64) -> f64 {
+    0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1)
+}
+
+pub fn cie_z(lambda: f64) -> f64 {
+    1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8)
+}
+
+// Integrates `f` over the spectral range in 1nm steps.
+fn integrate(f: impl Fn(f64) -> f64) -> f64 {
+    (LAMBDA_MIN as i32..=LAMBDA_MAX as i32)
+        .map(|l| f(l as f64))
+        .sum()
+}
+
+static CIE_Y_INTEGRAL: LazyLock<f64> = LazyLock::new(|| integrate(cie_y));
+
+// Normalizes D65 so that it has unit luminance.
+static D65_SCALE: LazyLock<f64> =
+    LazyLock::new(|| *CIE_Y_INTEGRAL / integrate(|l| d65(l) * cie_y(l)));
+
+// Relative spectral power of CIE standard illuminant D65, 360-780nm in 10nm
+// steps.
+const D65: [f64; 43] = [
+    46.64, 52.09, 49.98, 54.65, 82.75, 91.49, 93.43, 86.68, 104.86, 117.01, 117.81, 114.86, 115.92,
+    108.81, 109.35, 107.80, 104.79, 107.69, 104.41, 104.05, 100.00, 96.33, 95.79, 88.69, 90.01,
+    89.60, 87.70, 83.29, 83.70, 80.03, 80.21, 82.28, 78.28, 69.72, 71.61, 74.35, 61.60, 69.89,
+    75.09, 63.59, 46.42, 66.81, 63.38,
+];
+
+pub fn d65(lambda: f64) -> f64 {
+    let x = ((lambda - 360.0) / 10.0).clamp(0.0, (D65.len() - 1) as f64);
+    let i = (x as usize).min(D65.len() - 2);
+    let t = x - i as f64;
+
+    (1.0 - t) * D65[i] + t * D65[i + 1]
+}
+
+// RGB to spectrum conversion after Smits (1999): the color is built from a
+// white spectrum plus one secondary (cyan, magenta, yellow) and one primary
+// (red, green, blue), each a smooth reflectance tabulated in ten bins from
+// 380 to 720nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = (((lambda - 380.0) / 34.0).max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    let (white, secondary, primary) = if r <= g && r <= b {
+        if g <= b {
+            (r, (SMITS_CYAN, g - r), (SMITS_BLUE, b - g))
+        } else {
+            (r, (SMITS_CYAN, b - r), (SMITS_GREEN, g - b))
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            (g, (SMITS_MAGENTA, r - g), (SMITS_BLUE, b - r))
+        } else {
+            (g, (SMITS_MAGENTA, b - g), (SMITS_RED, r - b))
+        }
+    } else if r <= g {
+        (b, (SMITS_YELLOW, r - b), (SMITS_GREEN, g - r))
+    } else {
+        (b, (SMITS_YELLOW, g - b), (SMITS_RED, r - g))
+    };
+
+    (white * SMITS_WHITE[bin] + secondary.1 * secondary.0[bin] + primary.1 * primary.0[bin])
+        .max(0.0)
+}
+
+// Spectral radiance of a blackbody at `kelvin` by Planck's law, in W/(sr m^2)
+// per meter of wavelength.
+pub fn blackbody(lambda: f64, kelvin: f64) -> f64 {
+    const C: f64 = 299792458.0;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+
+    let l = lambda * 1e-9;
+
+    2.0 * H * C * C / (l.powi(5) * ((H * C / (l * KB * kelvin)).exp() - 1.0))
+}
+
+// Luminance of `blackbody` in the units `to_rgb` produces, for normalizing it.
+pub fn blackbody_luminance(kelvin: f64) -> f64 {
+    integrate(|l| blackbody(l, kelvin) * cie_y(l)) / *CIE_Y_INTEGRAL
+}
+
+// The linear sRGB color of a blackbody at `kelvin`, at unit luminance.
+pub fn blackbody_rgb(kelvin: f64) -> Color {
+    spectrum_rgb(|l| blackbody(l, kelvin)) / blackbody_luminance(kelvin)
+}
+
+// The linear sRGB color of the spectral distribution `f`, in the units
+// `to_rgb` produces.
+pub fn spectrum_rgb(f: impl Fn(f64) -> f64) -> Color {
+    let xyz = Color::new(
+        integrate(|l| f(l) * cie_x(l)),
+        integrate(|l| f(l) * cie_y(l)),
+        integrate(|l| f(l) * cie_z(l)),
+    );
+
+    xyz_to_rgb(xyz / *CIE_Y_INTEGRAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Color, b: Color, tolerance: f64) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < tolerance, "{a:?} != {b:?}");
+        }
+    }
+
+    // `to_rgb` averaged over evenly spread hero wavelengths.
+    fn round_trip(rgb: Color) -> Color {
+        let n = 10_000;
+        let sum = (0..n).fold(Color::new(0.0, 0.0, 0.0), |sum, k| {
+            let lambda = SampledWavelengths::sample_visible((k as f64 + 0.5) / n as f64);
+            sum + SampledSpectrum::from_illuminant(rgb, &lambda).to_rgb(&lambda)
+        });
+
+        sum / n as f64
+    }
+
+    #[test]
+    fn white_light_round_trips() {
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        assert_close(round_trip(white), white, 0.02);
+        assert_close(round_trip(4.0 * white), 4.0 * white, 0.08);
+    }
+
+    #[test]
+    fn d65_is_white_at_unit_luminance() {
+        let rgb = spectrum_rgb(|l| *D65_SCALE * d65(l));
+
+        assert_close(rgb, Color::new(1.0, 1.0, 1.0), 0.02);
+    }
+
+    #[test]
+    fn white_reflectance_is_flat() {
+        for lambda in (380..=720).step_by(10) {
+            let r = rgb_to_spectrum(Color::new(1.0, 1.0, 1.0), lambda as f64);
+
+            assert!((r - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn black_is_black() {
+        let lambda = SampledWavelengths::sample_visible(0.5);
+        let rgb =
+            SampledSpectrum::from_illuminant(Color::new(0.0, 0.0, 0.0), &lambda).to_rgb(&lambda);
+
+        assert_close(rgb, Color::new(0.0, 0.0, 0.0), 1e-12);
+    }
+}
@@ -1,13 +1,12 @@
 use core::f64;
 
-use rand::Rng;
-
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{self, HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::sampler;
 use crate::vec3::{self, Point3, Vec3};
 
 pub struct Sphere {
@@ -18,9 +17,10 @@ pub struct Sphere {
 }
 
 impl Sphere {
-    pub fn new_static(center: Point3, radius: f64, mat: Box<dyn Material>) -> Self {
+    pub fn new_static(center: Point3, radius: f64, mut mat: Box<dyn Material>) -> Self {
         let rvec = Vec3::new(radius, radius, radius);
         let bbox = AABB::from_points(center - rvec, center + rvec);
+        mat.set_emitter_area(sphere_area(radius));
 
         Sphere {
             center: Ray::new(center, Vec3::new(0.0, 0.0, 0.0), 0.0),
@@ -34,9 +34,10 @@ impl Sphere {
         center1: Point3,
         center2: Point3,
         radius: f64,
-        mat: Box<dyn Material>,
+        mut mat: Box<dyn Material>,
     ) -> Self {
         let rvec = Vec3::new(radius, radius, radius);
+        mat.set_emitter_area(sphere_area(radius));
         let center = Ray::new(center1, center2 - center1, 0.0);
         let box1 = AABB::from_points(center.at(0.0) - rvec, center.at(0.0) + rvec);
         let box2 = AABB::from_points(center.at(1.0) - rvec, center.at(1.0) + rvec);
@@ -51,6 +52,51 @@ impl Sphere {
     }
 }
 
+fn sphere_area(radius: f64) -> f64 {
+    4.0 * f64::consts::PI * radius * radius
+}
+
+impl Sphere {
+    fn area(&self) -> f64 {
+        sphere_area(self.radius)
+    }
+
+    // The point at (s, t) in the unit square, mapped to the sphere by equal
+    // areas, with its outward normal.
+    fn record_at(&self, s: f64, t: f64) -> HitRecord<'_> {
+        let z = 1.0 - 2.0 * s;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * f64::consts::PI * t;
+        let outward_normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+        let p = self.center.at(0.0) + self.radius * outward_normal;
+        let (u, v) = get_sphere_uv(outward_normal);
+        let (tangent, bitangent) = self.sphere_tangents(outward_normal);
+
+        HitRecord::new(p, outward_normal, self.mat.as_ref(), 0.0, u, v, true)
+            .with_tangents(tangent, bitangent)
+    }
+
+    // Derivatives of the point in `get_sphere_uv`'s u and v, which vanish at
+    // the poles.
+    fn sphere_tangents(&self, p: Point3) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - p.y() * p.y()).max(0.0).sqrt();
+        if sin_theta < 1e-8 {
+            return (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        }
+
+        let tangent = 2.0 * f64::consts::PI * self.radius * Vec3::new(p.z(), 0.0, -p.x());
+        let bitangent = f64::consts::PI
+            * self.radius
+            * Vec3::new(
+                -p.x() * p.y() / sin_theta,
+                sin_theta,
+                -p.z() * p.y() / sin_theta,
+            );
+
+        (tangent, bitangent)
+    }
+}
+
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let current_center = self.center.at(r.time());
@@ -84,16 +130,12 @@ impl Hittable for Sphere {
         };
 
         let (u, v) = get_sphere_uv(outward_normal);
+        let (tangent, bitangent) = self.sphere_tangents(outward_normal);
 
-        Some(HitRecord::new(
-            p,
-            normal,
-            self.mat.as_ref(),
-            root,
-            u,
-            v,
-            front_face,
-        ))
+        Some(
+            HitRecord::new(p, normal, self.mat.as_ref(), root, u, v, front_face)
+                .with_tangents(tangent, bitangent),
+        )
     }
 
     fn bounding_box(&self) -> AABB {
@@ -122,6 +164,39 @@ impl Hittable for Sphere {
 
         uvw.transform(random_to_sphere(self.radius, distance_squared))
     }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        let outward_normal = vec3::random_unit_vector();
+        let p = self.center.at(0.0) + self.radius * outward_normal;
+        let (u, v) = get_sphere_uv(outward_normal);
+        let (tangent, bitangent) = self.sphere_tangents(outward_normal);
+        let rec = HitRecord::new(p, outward_normal, self.mat.as_ref(), 0.0, u, v, true)
+            .with_tangents(tangent, bitangent);
+
+        Some((rec, 1.0 / self.area()))
+    }
+
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self
+            .hit(
+                &Ray::new(origin, direction, 0.0),
+                Interval::new(0.001, f64::INFINITY),
+            )
+            .is_some()
+        {
+            1.0 / self.area()
+        } else {
+            0.0
+        }
+    }
+
+    fn power(&self) -> f64 {
+        hittable::emitted_power(self.area(), |s, t| self.record_at(s, t))
+    }
+
+    fn emits(&self) -> bool {
+        self.mat.is_emissive()
+    }
 }
 
 fn get_sphere_uv(p: Point3) -> (f64, f64) {
@@ -135,8 +210,8 @@ fn get_sphere_uv(p: Point3) -> (f64, f64) {
 }
 
 fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
-    let r1 = rand::rng().random::<f64>();
-    let r2 = rand::rng().random::<f64>();
+    let r1 = sampler::random_double();
+    let r2 = sampler::random_double();
     let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
 
     let phi = 2.0 * f64::consts::PI * r1;
@@ -0,0 +1,172 @@
+use crate::aabb::AABB;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::{Dielectric, Isotropic, Material, ScatterRecord};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+// Random-walk subsurface scattering for wax, jade, marble or skin (after
+// Chiang et al. 2016). Light refracts into the closed `boundary` through a
+// smooth dielectric, then takes exponentially distributed free flights
+// between isotropic scattering events until it refracts back out. Unlike
+// `ConstantMedium`, whether a ray is inside comes from the media it has
+// entered, so non-convex meshes work too.
+pub struct Subsurface {
+    boundary: Box<dyn Hittable>,
+    interface: Arc<Dielectric>,
+    phase_function: Isotropic,
+    sigma_t: f64,
+}
+
+impl Subsurface {
+    // `albedo` is the color the surface ends up with after many bounces, and
+    // `mean_free_path` the average distance between them. `make_boundary`
+    // builds the closed surface, calling for its material once per part.
+    pub fn new<F>(
+        albedo: Color,
+        mean_free_path: f64,
+        refraction_index: f64,
+        make_boundary: F,
+    ) -> Self
+    where
+        F: FnOnce(&dyn Fn() -> Box<dyn Material>) -> Box<dyn Hittable>,
+    {
+        let single_scattering = |a: f64| {
+            let a = a.clamp(0.0, 1.0);
+            let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+
+            1.0 - s * s
+        };
+
+        let interface = Arc::new(Dielectric::new(refraction_index));
+        let boundary = make_boundary(&|| Box::new(Interface(interface.clone())));
+
+        Self {
+            boundary,
+            interface,
+            phase_function: Isotropic::from_color(Color::new(
+                single_scattering(albedo.x()),
+                single_scattering(albedo.y()),
+                single_scattering(albedo.z()),
+            )),
+            sigma_t: 1.0 / mean_free_path,
+        }
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let surface = self.boundary.hit(r, ray_t);
+
+        if self.interface.contains(r) {
+            let ray_length = r.direction().length();
+            let distance = -sampler::random_double().ln() / self.sigma_t;
+            let t = ray_t.min + distance / ray_length;
+
+            if t < ray_t.max && surface.as_ref().is_none_or(|rec| t < rec.t) {
+                return Some(HitRecord::new(
+                    r.at(t),
+                    Vec3::new(1.0, 0.0, 0.0),
+                    &self.phase_function,
+                    t,
+                    0.0,
+                    0.0,
+                    true,
+                ));
+            }
+        }
+
+        surface
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.boundary.bounding_box()
+    }
+}
+
+// The material of every part of a `Subsurface`'s boundary: one dielectric,
+// so that rays leave through any part the medium they entered through
+// another.
+struct Interface(Arc<Dielectric>);
+
+impl Material for Interface {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.0.scatter(r_in, rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{self, Camera};
+    use crate::hittable_list::HittableList;
+    use crate::material::DiffuseLight;
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    // A white furnace: under uniform white light, a medium that absorbs
+    // nothing looks uniformly white, however long its random walks are. A
+    // faint light in the light list makes the walks choose between sampling
+    // it and the phase function.
+    #[test]
+    fn white_medium_conserves_energy() {
+        // Each step of a walk is a level of recursion, too many for the
+        // default test thread's stack in debug builds.
+        std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(furnace)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn furnace() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mut world = HittableList::new();
+        world.add(Box::new(Subsurface::new(white, 10.0, 1.5, |surface| {
+            Box::new(Sphere::new_static(
+                Point3::new(0.0, 0.0, 0.0),
+                90.0,
+                surface(),
+            ))
+        })));
+        world.add(Box::new(Quad::new(
+            Point3::new(-50.0, 300.0, -50.0),
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 100.0),
+            Box::new(DiffuseLight::from_color(white * 1e-9)),
+        )));
+        let lights = HittableList::lights_from(&world);
+
+        let max_depth = 500;
+        let cam = Camera::new(
+            1.0,
+            10.0,
+            1,
+            max_depth,
+            white,
+            40,
+            Point3::new(0.0, 0.0, -500.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            500.0,
+        );
+
+        let n = 10_000;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let x = (i % 100) as f64 - 50.0;
+            let r = Ray::new(Point3::new(x, 0.0, -500.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+            sum += camera::ray_color(&cam, r, max_depth, &world, &lights);
+        }
+
+        let mean = sum.x() / n as f64;
+        assert!((mean - 1.0).abs() < 0.02, "mean {mean}");
+    }
+}
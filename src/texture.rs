@@ -20,6 +20,11 @@ impl SolidColor {
 
         Self { albedo }
     }
+
+    // A gray level, for textures that drive scalar parameters.
+    pub fn from_value(value: f64) -> Self {
+        Self::from_rgb(value, value, value)
+    }
 }
 
 impl Texture for SolidColor {
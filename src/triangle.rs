@@ -1,10 +1,11 @@
 use crate::aabb::AABB;
-use crate::hittable::{HitRecord, Hittable};
+use crate::hittable::{self, HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::light::DirectionCone;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{self, Point3, Vec3};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 pub struct Triangle {
     v0: Point3,
@@ -17,10 +18,24 @@ pub struct Triangle {
     bbox: AABB,
     normal: Vec3,
     D: f64,
+    area: f64,
+    uvs: [(f64, f64); 3],
+    tangent: Vec3,
+    bitangent: Vec3,
+    coverage: OnceLock<f64>,
 }
 
 impl Triangle {
-    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Material>) -> Self {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mut mat: Box<dyn Material>) -> Self {
+        mat.set_emitter_area(0.5 * vec3::cross(v1 - v0, v2 - v0).length());
+
+        Self::shared(v0, v1, v2, Arc::from(mat))
+    }
+
+    // A triangle of a mesh, with the material all its triangles share. An
+    // emitter given a total power has to be told the area of the whole mesh
+    // beforehand, as `load_obj` does.
+    pub fn shared(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Material>) -> Self {
         let u = v1 - v0;
         let v = v2 - v0;
         let bbox = set_bounding_box(v0, v1, v2);
@@ -28,6 +43,7 @@ impl Triangle {
         let normal = vec3::unit_vector(n);
         let D = vec3::dot(normal, v0);
         let w = n / vec3::dot(n, n);
+        let area = 0.5 * n.length();
 
         Self {
             v0,
@@ -40,8 +56,72 @@ impl Triangle {
             bbox,
             normal,
             D,
+            area,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            tangent: u,
+            bitangent: v,
+            coverage: OnceLock::new(),
+        }
+    }
+
+    // Texture coordinates at each vertex; without them u and v are the
+    // barycentric coordinates of v1 and v2.
+    pub fn with_uvs(self, uv0: (f64, f64), uv1: (f64, f64), uv2: (f64, f64)) -> Self {
+        let duv1 = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+        let duv2 = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+        let det = duv1.0 * duv2.1 - duv1.1 * duv2.0;
+
+        // Degenerate mappings keep the edge directions as the tangent frame.
+        let (tangent, bitangent) = if det.abs() < 1e-12 {
+            (self.u, self.v)
+        } else {
+            (
+                (duv2.1 * self.u - duv1.1 * self.v) / det,
+                (duv1.0 * self.v - duv2.0 * self.u) / det,
+            )
+        };
+
+        Self {
+            uvs: [uv0, uv1, uv2],
+            tangent,
+            bitangent,
+            ..self
         }
     }
+
+    // The point at (s, t) in the unit square, folded onto the triangle.
+    fn record_at(&self, s: f64, t: f64) -> HitRecord<'_> {
+        let (alpha, beta) = if s + t > 1.0 {
+            (1.0 - s, 1.0 - t)
+        } else {
+            (s, t)
+        };
+
+        let p = self.v0 + (alpha * self.u) + (beta * self.v);
+        let (u, v) = self.uv(alpha, beta);
+
+        HitRecord::new(p, self.normal, self.mat.as_ref(), 0.0, u, v, true)
+            .with_tangents(self.tangent, self.bitangent)
+    }
+
+    // Area left by the material's opacity, worked out the first time a light
+    // sample needs it.
+    fn visible_area(&self) -> f64 {
+        self.area
+            * *self
+                .coverage
+                .get_or_init(|| hittable::opaque_fraction(|s, t| self.record_at(s, t)))
+    }
+
+    fn uv(&self, alpha: f64, beta: f64) -> (f64, f64) {
+        let gamma = 1.0 - alpha - beta;
+        let [uv0, uv1, uv2] = self.uvs;
+
+        (
+            gamma * uv0.0 + alpha * uv1.0 + beta * uv2.0,
+            gamma * uv0.1 + alpha * uv1.1 + beta * uv2.1,
+        )
+    }
 }
 
 impl Hittable for Triangle {
@@ -73,20 +153,70 @@ impl Hittable for Triangle {
             -self.normal
         };
 
-        Some(HitRecord::new(
-            intersection,
-            normal,
-            self.mat.as_ref(),
-            t,
-            alpha,
-            beta,
-            front_face,
-        ))
+        let (u, v) = self.uv(alpha, beta);
+
+        let rec = HitRecord::new(intersection, normal, self.mat.as_ref(), t, u, v, front_face)
+            .with_tangents(self.tangent, self.bitangent);
+
+        Some(rec).filter(|rec| rec.is_opaque())
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if let Some(rec) = self.hit(
+            &Ray::new(origin, direction, 0.0),
+            Interval::new(0.001, f64::INFINITY),
+        ) {
+            let distance_squared = rec.t * rec.t * direction.length_squared();
+            let cosine = (vec3::dot(direction, rec.normal) / direction.length()).abs();
+
+            distance_squared / (cosine * self.visible_area())
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        match hittable::random_opaque(|s, t| self.record_at(s, t)) {
+            Some(rec) => rec.p - origin,
+            None => self.v0 - origin,
+        }
+    }
+
+    fn random_on_surface(&self) -> Option<(HitRecord<'_>, f64)> {
+        let rec = hittable::random_opaque(|s, t| self.record_at(s, t))?;
+
+        Some((rec, 1.0 / self.visible_area()))
+    }
+
+    fn area_pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self
+            .hit(
+                &Ray::new(origin, direction, 0.0),
+                Interval::new(0.001, f64::INFINITY),
+            )
+            .is_some()
+        {
+            1.0 / self.visible_area()
+        } else {
+            0.0
+        }
+    }
+
+    fn power(&self) -> f64 {
+        hittable::emitted_power(self.area, |s, t| self.record_at(s, t))
+    }
+
+    fn emits(&self) -> bool {
+        self.mat.is_emissive()
+    }
+
+    fn emission_cone(&self) -> DirectionCone {
+        DirectionCone::emitted_from(self.normal, self.mat.emission_sides())
+    }
 }
 
 fn set_bounding_box(v0: Point3, v1: Point3, v2: Point3) -> AABB {
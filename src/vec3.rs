@@ -1,5 +1,5 @@
+use crate::sampler;
 use core::f64;
-use rand::Rng;
 use std::fmt;
 use std::ops::Add;
 use std::ops::AddAssign;
@@ -55,8 +55,8 @@ pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
 pub fn random_in_unit_disk() -> Vec3 {
     loop {
         let p = Vec3::new(
-            rand::rng().random_range(-1.0..1.0),
-            rand::rng().random_range(-1.0..1.0),
+            sampler::random_double_range(-1.0, 1.0),
+            sampler::random_double_range(-1.0, 1.0),
             0.0,
         );
 
@@ -80,23 +80,23 @@ pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
 
 pub fn random() -> Vec3 {
     Vec3::new(
-        rand::rng().random(),
-        rand::rng().random(),
-        rand::rng().random(),
+        sampler::random_double(),
+        sampler::random_double(),
+        sampler::random_double(),
     )
 }
 
 pub fn random_range(min: f64, max: f64) -> Vec3 {
     Vec3::new(
-        rand::rng().random_range(min..max),
-        rand::rng().random_range(min..max),
-        rand::rng().random_range(min..max),
+        sampler::random_double_range(min, max),
+        sampler::random_double_range(min, max),
+        sampler::random_double_range(min, max),
     )
 }
 
 pub fn random_cosine_direction() -> Vec3 {
-    let r1: f64 = rand::rng().random();
-    let r2: f64 = rand::rng().random();
+    let r1 = sampler::random_double();
+    let r2 = sampler::random_double();
 
     let phi = 2.0 * f64::consts::PI * r1;
     let x = phi.cos() * r2.sqrt();